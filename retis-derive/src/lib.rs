@@ -42,6 +42,12 @@ pub fn event_section(
                 serde_json::json!(self)
             }
 
+            fn to_cbor(&self, buf: &mut Vec<u8>) -> anyhow::Result<()>
+                where Self: serde::Serialize,
+            {
+                Ok(ciborium::into_writer(self, buf)?)
+            }
+
             #[cfg(feature = "python")]
             fn to_py(&self, py: pyo3::Python<'_>) -> pyo3::PyObject {
                 use pyo3::IntoPy;
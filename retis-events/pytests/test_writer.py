@@ -0,0 +1,58 @@
+from retis import Event, EventFile, EventReader, EventWriter, SeriesReader
+
+import pytest
+
+
+def test_event_writer(tmp_path):
+    """Test EventWriter writes events that can be read back"""
+    out = tmp_path / "events.json"
+    events = list(EventReader("test_data/test_events.json"))
+
+    with EventWriter(str(out)) as w:
+        for e in events:
+            w.write(e)
+
+    written = list(EventReader(str(out)))
+    assert len(written) == len(events)
+    for e, w in zip(events, written):
+        assert e.raw() == w.raw()
+
+
+def test_event_modify():
+    """Test events can be created from (modified) raw data"""
+    e = next(iter(EventReader("test_data/test_events.json")))
+    raw = e.raw()
+    raw["common"]["timestamp"] = 42
+
+    e = Event(raw)
+    assert e.get_field("common.timestamp") == 42
+
+    with pytest.raises(Exception):
+        Event({"foo": {}})
+
+
+def test_writer_type(tmp_path):
+    """Test events and series can't be mixed in the same file"""
+    e = next(iter(EventReader("test_data/test_events.json")))
+    s = next(iter(SeriesReader("test_data/test_events_sorted.json")))
+
+    w = EventWriter(str(tmp_path / "mixed.json"))
+    w.write(e)
+    with pytest.raises(TypeError):
+        w.write(s)
+
+
+def test_event_file_filter(tmp_path):
+    """Test EventFile can filter events and series to a new file"""
+    out = tmp_path / "filtered.json"
+    f = EventFile("test_data/test_events.json")
+    n = f.filter(lambda e: "kernel" in e, str(out))
+    assert n == sum(1 for e in f.events() if "kernel" in e)
+    assert all("kernel" in e for e in EventReader(str(out)))
+
+    out = tmp_path / "filtered_sorted.json"
+    sf = EventFile("test_data/test_events_sorted.json")
+    n = sf.filter(lambda s: len(s) > 1, str(out))
+    filtered = EventFile(str(out))
+    assert filtered.sorted()
+    assert n == sum(1 for _ in filtered.series())
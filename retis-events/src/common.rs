@@ -12,14 +12,74 @@ pub struct StartupEvent {
     pub retis_version: String,
     /// CLOCK_MONOTONIC offset in regards to local machine time.
     pub clock_monotonic_offset: TimeSpec,
+    /// Sampling & rate limiting configuration, if events were sampled.
+    pub sampling: Option<SamplingConfig>,
 }
 
 impl EventFmt for StartupEvent {
     fn event_fmt(&self, f: &mut Formatter, _: &DisplayFormat) -> fmt::Result {
-        write!(f, "Retis version {}", self.retis_version)
+        write!(f, "Retis version {}", self.retis_version)?;
+
+        if let Some(sampling) = &self.sampling {
+            if let Some(rate) = sampling.sample_rate {
+                write!(f, " sampling 1/{rate}")?;
+            }
+            if let Some(limit) = sampling.rate_limit {
+                write!(f, " rate-limit {limit}/s")?;
+            }
+            write!(
+                f,
+                " per {}",
+                if sampling.per_flow { "flow" } else { "probe" }
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Marker event section. Injected on request while collecting events, e.g. to
+/// delimit a test run in the collected events.
+#[event_section(SectionId::Marker)]
+pub struct MarkerEvent {
+    /// Message attached to the marker.
+    pub message: String,
+}
+
+impl EventFmt for MarkerEvent {
+    fn event_fmt(&self, f: &mut Formatter, _: &DisplayFormat) -> fmt::Result {
+        write!(f, "marker: {}", self.message)
     }
 }
 
+/// Origin event section. Added when merging events from multiple files (e.g.
+/// collected on different hosts) to identify where each event comes from.
+#[event_section(SectionId::Origin)]
+pub struct OriginEvent {
+    /// Label of the origin of the event, e.g. a host or a file name.
+    pub label: String,
+}
+
+impl EventFmt for OriginEvent {
+    fn event_fmt(&self, f: &mut Formatter, _: &DisplayFormat) -> fmt::Result {
+        write!(f, "origin {}", self.label)
+    }
+}
+
+/// Sampling & rate limiting configuration of a collection. Statistics computed
+/// on sampled events can be rescaled using those parameters.
+#[event_type]
+#[derive(Default)]
+pub struct SamplingConfig {
+    /// One event out of `sample_rate` was kept.
+    pub sample_rate: Option<u32>,
+    /// Maximum number of events kept per second.
+    pub rate_limit: Option<u32>,
+    /// Sampling & rate limiting were applied per packet (skb tracking id)
+    /// rather than per probe.
+    pub per_flow: bool,
+}
+
 /// Information about a given task.
 #[event_type]
 #[derive(Default)]
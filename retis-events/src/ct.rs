@@ -99,6 +99,22 @@ pub enum CtState {
     #[default]
     Untracked,
 }
+
+impl CtState {
+    /// Name of the state, as found in the serialized events.
+    pub fn name(&self) -> &'static str {
+        use CtState::*;
+        match self {
+            Established => "established",
+            Related => "related",
+            New => "new",
+            Reply => "reply",
+            RelatedReply => "related_reply",
+            Untracked => "untracked",
+        }
+    }
+}
+
 /// Conntrack event
 #[event_section(SectionId::Ct)]
 pub struct CtEvent {
@@ -1,5 +1,6 @@
 //! Internal representation of events. Those events can be marshaled/unmarshaled
-//! to other formats to be stored or displayed. We currently support: JSON.
+//! to other formats to be stored or displayed. We currently support: JSON and a
+//! compact binary format (see `Event::to_binary`).
 //!
 //! As an example, a full JSON output should look like:
 //!
@@ -56,14 +57,16 @@ impl Event {
     /// Create an Event from a json object.
     pub(crate) fn from_json_obj(mut obj: HashMap<String, serde_json::Value>) -> Result<Event> {
         let mut event = Event::new();
+        let sections = event_sections()?;
 
         for (owner, value) in obj.drain() {
-            let parser = event_sections()?
-                .get(&owner)
+            let parser = SectionId::from_str(&owner)
+                .ok()
+                .and_then(|id| sections.get(&id))
                 .ok_or_else(|| anyhow!("json contains an unsupported event {}", owner))?;
 
             debug!("Unmarshaling event section {owner}: {value}");
-            let section = parser(value).map_err(|e| {
+            let section = (parser.from_json)(value).map_err(|e| {
                 anyhow!("Failed to create EventSection for owner {owner} from json: {e}")
             })?;
             event.insert_section(SectionId::from_u8(section.id())?, section)?;
@@ -79,6 +82,51 @@ impl Event {
         Self::from_json_obj(event_js)
     }
 
+    /// Encode the event in the binary format, appending it to `buf`. Each
+    /// section is stored as its id (u8), the length of its data (u32, little
+    /// endian) and its data encoded in CBOR.
+    pub fn to_binary(&self, buf: &mut Vec<u8>) -> Result<()> {
+        for (owner, section) in self.0.iter() {
+            buf.push(*owner as u8);
+
+            let len_off = buf.len();
+            buf.extend_from_slice(&[0; 4]);
+            section.to_cbor(buf)?;
+
+            let len = u32::try_from(buf.len() - len_off - 4)
+                .map_err(|_| anyhow!("Section {owner} is too large"))?;
+            buf[len_off..(len_off + 4)].copy_from_slice(&len.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    /// Create an Event from its binary representation.
+    pub fn from_binary(mut buf: &[u8]) -> Result<Event> {
+        let mut event = Event::new();
+
+        while !buf.is_empty() {
+            if buf.len() < 5 {
+                bail!("Truncated binary event section");
+            }
+            let owner = SectionId::from_u8(buf[0])?;
+            let len = u32::from_le_bytes(buf[1..5].try_into()?) as usize;
+            let data = buf
+                .get(5..(5 + len))
+                .ok_or_else(|| anyhow!("Truncated binary event section {owner}"))?;
+
+            let parser = event_sections()?
+                .get(&owner)
+                .ok_or_else(|| anyhow!("binary data contains an unsupported event {owner}"))?;
+            let section = (parser.from_cbor)(data).map_err(|e| {
+                anyhow!("Failed to create EventSection for owner {owner} from binary: {e}")
+            })?;
+            event.insert_section(owner, section)?;
+
+            buf = &buf[(5 + len)..];
+        }
+        Ok(event)
+    }
+
     /// Insert a new event field into an event.
     pub fn insert_section(
         &mut self,
@@ -207,8 +255,11 @@ pub enum SectionId {
     Nft = 9,
     Ct = 10,
     Startup = 11,
+    Lost = 12,
+    Marker = 13,
+    Origin = 14,
     // TODO: use std::mem::variant_count once in stable.
-    _MAX = 12,
+    _MAX = 15,
 }
 
 impl SectionId {
@@ -227,6 +278,9 @@ impl SectionId {
             9 => Nft,
             10 => Ct,
             11 => Startup,
+            12 => Lost,
+            13 => Marker,
+            14 => Origin,
             x => bail!("Can't construct a SectionId from {}", x),
         })
     }
@@ -246,6 +300,9 @@ impl SectionId {
             Nft => "nft",
             Ct => "ct",
             Startup => "startup",
+            Lost => "lost",
+            Marker => "marker",
+            Origin => "origin",
             _MAX => "_max",
         }
     }
@@ -276,19 +333,31 @@ impl FromStr for SectionId {
             "nft" => Nft,
             "ct" => Ct,
             "startup" => Startup,
+            "lost" => Lost,
+            "marker" => Marker,
+            "origin" => Origin,
             x => bail!("Can't construct a SectionId from {}", x),
         })
     }
 }
 
-type EventSectionMap = HashMap<String, fn(serde_json::Value) -> Result<Box<dyn EventSection>>>;
+/// Unmarshaling helpers of an event section.
+struct EventSectionParsers {
+    from_json: fn(serde_json::Value) -> Result<Box<dyn EventSection>>,
+    from_cbor: fn(&[u8]) -> Result<Box<dyn EventSection>>,
+}
+
+type EventSectionMap = HashMap<SectionId, EventSectionParsers>;
 static EVENT_SECTIONS: OnceCell<EventSectionMap> = OnceCell::new();
 
 macro_rules! insert_section {
     ($events: expr, $ty: ty) => {
         $events.insert(
-            SectionId::from_u8(<$ty>::SECTION_ID)?.to_str().to_string(),
-            |v| Ok(Box::new(serde_json::from_value::<$ty>(v)?)),
+            SectionId::from_u8(<$ty>::SECTION_ID)?,
+            EventSectionParsers {
+                from_json: |v| Ok(Box::new(serde_json::from_value::<$ty>(v)?)),
+                from_cbor: |v| Ok(Box::new(ciborium::from_reader::<$ty, _>(v)?)),
+            },
         );
     };
 }
@@ -307,6 +376,9 @@ fn event_sections() -> Result<&'static EventSectionMap> {
         insert_section!(events, NftEvent);
         insert_section!(events, CtEvent);
         insert_section!(events, StartupEvent);
+        insert_section!(events, LostEvent);
+        insert_section!(events, MarkerEvent);
+        insert_section!(events, OriginEvent);
         insert_section!(events, TrackingInfo);
 
         Ok(events)
@@ -337,6 +409,7 @@ pub trait EventSectionInternal {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
     fn to_json(&self) -> serde_json::Value;
+    fn to_cbor(&self, buf: &mut Vec<u8>) -> Result<()>;
     #[cfg(feature = "python")]
     fn to_py(&self, py: pyo3::Python<'_>) -> pyo3::PyObject;
 }
@@ -360,6 +433,10 @@ impl EventSectionInternal for () {
         serde_json::Value::Null
     }
 
+    fn to_cbor(&self, buf: &mut Vec<u8>) -> Result<()> {
+        Ok(ciborium::into_writer(self, buf)?)
+    }
+
     #[cfg(feature = "python")]
     fn to_py(&self, py: pyo3::Python<'_>) -> pyo3::PyObject {
         py.None()
@@ -393,6 +470,41 @@ impl EventSeries {
         }
         Ok(series)
     }
+
+    /// Encode the EventSeries in the binary format, appending it to `buf`.
+    /// Each event is stored as its length (u32, little endian) followed by
+    /// its binary representation.
+    pub fn to_binary(&self, buf: &mut Vec<u8>) -> Result<()> {
+        for event in self.events.iter() {
+            let len_off = buf.len();
+            buf.extend_from_slice(&[0; 4]);
+            event.to_binary(buf)?;
+
+            let len = u32::try_from(buf.len() - len_off - 4)
+                .map_err(|_| anyhow!("Event is too large"))?;
+            buf[len_off..(len_off + 4)].copy_from_slice(&len.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    /// Create an EventSeries from its binary representation.
+    pub(crate) fn from_binary(mut buf: &[u8]) -> Result<EventSeries> {
+        let mut series = EventSeries::default();
+
+        while !buf.is_empty() {
+            if buf.len() < 4 {
+                bail!("Truncated binary event in series");
+            }
+            let len = u32::from_le_bytes(buf[0..4].try_into()?) as usize;
+            let data = buf
+                .get(4..(4 + len))
+                .ok_or_else(|| anyhow!("Truncated binary event in series"))?;
+
+            series.events.push(Event::from_binary(data)?);
+            buf = &buf[(4 + len)..];
+        }
+        Ok(series)
+    }
 }
 
 #[cfg(feature = "test-events")]
@@ -0,0 +1,241 @@
+//! # Field
+//!
+//! Generic access to the fields of an event, addressed by their path (e.g.
+//! "skb.tcp.dport"), without having to know the concrete type of the
+//! sections. Values are retrieved from the serde representation of the
+//! sections.
+
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::*;
+
+/// Path to an event field: a section followed by a list of (nested) fields.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldPath {
+    pub section: SectionId,
+    pub fields: Vec<String>,
+}
+
+impl FromStr for FieldPath {
+    type Err = anyhow::Error;
+
+    /// Parse a path following the SECTION[.FIELD...] format. Sections can be
+    /// given using their name (e.g. "skb-drop") or with underscores (e.g.
+    /// "skb_drop").
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split('.');
+        let section = parts.next().unwrap_or_default();
+        let section = SectionId::from_str(section)
+            .or_else(|_| SectionId::from_str(&section.replace('_', "-")))
+            .map_err(|_| anyhow!("Unknown section '{section}' in field path '{s}'"))?;
+
+        let fields: Vec<String> = parts.map(String::from).collect();
+        if fields.iter().any(|f| f.is_empty()) {
+            bail!("Empty field name in field path '{s}'");
+        }
+
+        Ok(Self { section, fields })
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.section.to_str())?;
+        self.fields
+            .iter()
+            .try_for_each(|field| write!(f, ".{field}"))
+    }
+}
+
+/// Value of an event field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    String(String),
+    List(Vec<FieldValue>),
+    /// Non-leaf fields, e.g. "skb.tcp".
+    Object(BTreeMap<String, FieldValue>),
+}
+
+impl FieldValue {
+    /// Convert a JSON value to a field value. Null values have no field value
+    /// representation.
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        use serde_json::Value;
+
+        Some(match value {
+            Value::Null => return None,
+            Value::Bool(b) => FieldValue::Bool(*b),
+            Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+                (Some(u), _) => FieldValue::Unsigned(u),
+                (_, Some(i)) => FieldValue::Signed(i),
+                _ => FieldValue::Float(n.as_f64()?),
+            },
+            Value::String(s) => FieldValue::String(s.clone()),
+            Value::Array(a) => FieldValue::List(a.iter().filter_map(Self::from_json).collect()),
+            Value::Object(o) => FieldValue::Object(
+                o.iter()
+                    .filter_map(|(k, v)| Some((k.clone(), Self::from_json(v)?)))
+                    .collect(),
+            ),
+        })
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value;
+
+        match self {
+            FieldValue::Bool(b) => Value::from(*b),
+            FieldValue::Unsigned(u) => Value::from(*u),
+            FieldValue::Signed(i) => Value::from(*i),
+            FieldValue::Float(f) => Value::from(*f),
+            FieldValue::String(s) => Value::from(s.as_str()),
+            FieldValue::List(l) => Value::Array(l.iter().map(|v| v.to_json()).collect()),
+            FieldValue::Object(o) => {
+                Value::Object(o.iter().map(|(k, v)| (k.clone(), v.to_json())).collect())
+            }
+        }
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Bool(b) => write!(f, "{b}"),
+            FieldValue::Unsigned(u) => write!(f, "{u}"),
+            FieldValue::Signed(i) => write!(f, "{i}"),
+            FieldValue::Float(x) => write!(f, "{x}"),
+            FieldValue::String(s) => write!(f, "{s}"),
+            FieldValue::List(_) | FieldValue::Object(_) => write!(f, "{}", self.to_json()),
+        }
+    }
+}
+
+impl Event {
+    /// Get the value of a field given its path (e.g. "skb.tcp.dport"), see
+    /// `FieldPath`. Returns `None` if the field is not set in the event and an
+    /// error if the path is invalid.
+    pub fn get_field(&self, path: &str) -> Result<Option<FieldValue>> {
+        Ok(self.get_field_path(&FieldPath::from_str(path)?))
+    }
+
+    /// Get the value of a field given its parsed path, see `get_field`. Use
+    /// `EventFields` when looking up multiple fields of the same event.
+    pub fn get_field_path(&self, path: &FieldPath) -> Option<FieldValue> {
+        EventFields::new(self).get(path)
+    }
+}
+
+/// Accessor to the fields of an event, caching the serde representation of
+/// its sections so looking up multiple fields does not convert the same
+/// section multiple times.
+pub struct EventFields<'a> {
+    event: &'a Event,
+    sections: HashMap<SectionId, serde_json::Value>,
+}
+
+impl<'a> EventFields<'a> {
+    pub fn new(event: &'a Event) -> Self {
+        Self {
+            event,
+            sections: HashMap::new(),
+        }
+    }
+
+    /// Get the value of a field given its parsed path, see
+    /// `Event::get_field_path`.
+    pub fn get(&mut self, path: &FieldPath) -> Option<FieldValue> {
+        let json = match self.sections.entry(path.section) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(self.event.get(path.section)?.to_json()),
+        };
+        let value = path
+            .fields
+            .iter()
+            .try_fold(&*json, |value, field| value.get(field.as_str()))?;
+
+        FieldValue::from_json(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> Event {
+        let mut event = Event::new();
+        event
+            .insert_section(
+                SectionId::Common,
+                Box::new(CommonEvent {
+                    timestamp: 42,
+                    ..Default::default()
+                }),
+            )
+            .unwrap();
+        event
+            .insert_section(
+                SectionId::SkbDrop,
+                Box::new(SkbDropEvent {
+                    subsys: None,
+                    drop_reason: "NETFILTER_DROP".to_string(),
+                }),
+            )
+            .unwrap();
+        event
+    }
+
+    #[test]
+    fn field_path() -> Result<()> {
+        let path = FieldPath::from_str("skb_drop.drop_reason")?;
+        assert_eq!(path.section, SectionId::SkbDrop);
+        assert_eq!(path.fields, vec!["drop_reason".to_string()]);
+        assert_eq!(path.to_string(), "skb-drop.drop_reason");
+
+        assert_eq!(FieldPath::from_str("skb")?.fields.len(), 0);
+        assert!(FieldPath::from_str("").is_err());
+        assert!(FieldPath::from_str("foo.bar").is_err());
+        assert!(FieldPath::from_str("skb..tcp").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn get_field() -> Result<()> {
+        let event = event();
+
+        assert_eq!(
+            event.get_field("common.timestamp")?,
+            Some(FieldValue::Unsigned(42))
+        );
+        assert_eq!(
+            event.get_field("skb-drop.drop_reason")?,
+            Some(FieldValue::String("NETFILTER_DROP".to_string()))
+        );
+        assert_eq!(event.get_field("skb-drop.subsys")?, None);
+        assert_eq!(event.get_field("skb.tcp.dport")?, None);
+        assert!(matches!(
+            event.get_field("skb-drop")?,
+            Some(FieldValue::Object(_))
+        ));
+        assert!(event.get_field("foo.bar").is_err());
+
+        let mut fields = EventFields::new(&event);
+        for _ in 0..2 {
+            assert_eq!(
+                fields.get(&FieldPath::from_str("skb-drop.drop_reason")?),
+                Some(FieldValue::String("NETFILTER_DROP".to_string()))
+            );
+            assert_eq!(fields.get(&FieldPath::from_str("skb.tcp.dport")?), None);
+        }
+        Ok(())
+    }
+}
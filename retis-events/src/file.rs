@@ -1,12 +1,15 @@
-//! Handles the file (json) to Rust event retrieval and the unmarshaling process.
+//! Handles the file (json or binary) to Rust event retrieval and the
+//! unmarshaling process. Event files can optionally be compressed, which is
+//! transparently handled.
 
 use std::{
     fs::File,
-    io::{BufRead, BufReader, Seek},
+    io::{BufRead, BufReader, Read, Write},
     path::Path,
 };
 
 use anyhow::{anyhow, bail, Result};
+use flate2::{bufread::MultiGzDecoder, write::GzEncoder};
 
 use super::{Event, EventSeries};
 
@@ -19,11 +22,111 @@ pub enum FileType {
     Series,
 }
 
+/// Compression algorithm used in an event file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileCompression {
+    /// Plain file.
+    #[default]
+    None,
+    /// Gzip compressed file.
+    Gzip,
+    /// Zstandard compressed file.
+    Zstd,
+}
+
+impl FileCompression {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+    const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+    /// Detect the compression used based on the first bytes of a file.
+    fn detect(magic: &[u8]) -> Self {
+        if magic.starts_with(&Self::GZIP_MAGIC) {
+            FileCompression::Gzip
+        } else if magic.starts_with(&Self::ZSTD_MAGIC) {
+            FileCompression::Zstd
+        } else {
+            FileCompression::None
+        }
+    }
+
+    /// Wrap a writer so data written to it is compressed. Compression is
+    /// finalized when the returned writer is dropped.
+    pub fn writer<W>(&self, writer: W) -> Result<Box<dyn Write>>
+    where
+        W: Write + 'static,
+    {
+        Ok(match self {
+            FileCompression::None => Box::new(writer),
+            FileCompression::Gzip => {
+                Box::new(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            FileCompression::Zstd => Box::new(zstd::Encoder::new(writer, 0)?.auto_finish()),
+        })
+    }
+}
+
+/// Format of the events stored in a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileFormat {
+    /// One JSON object (event) or array (series) per line.
+    #[default]
+    Json,
+    /// Binary header followed by length-prefixed records, each holding an
+    /// event or a series in their binary representation (see
+    /// `Event::to_binary`).
+    Binary,
+}
+
+/// Magic identifying binary event files. It is followed by the format version
+/// and the file type, both on a single byte.
+const BINARY_MAGIC: [u8; 8] = *b"RETISBIN";
+const BINARY_VERSION: u8 = 1;
+/// Maximum size of a binary record, so a corrupted or malicious length prefix
+/// can't trigger huge allocations.
+const MAX_RECORD_SIZE: usize = 256 << 20;
+
+/// Write the header of a binary event file.
+pub fn write_binary_header<W>(writer: &mut W, filetype: &FileType) -> Result<()>
+where
+    W: Write + ?Sized,
+{
+    let filetype = match filetype {
+        FileType::Event => 0,
+        FileType::Series => 1,
+    };
+
+    writer.write_all(&BINARY_MAGIC)?;
+    writer.write_all(&[BINARY_VERSION, filetype])?;
+    Ok(())
+}
+
+/// Write a record (an event or a series in its binary representation) to a
+/// binary event file.
+pub fn write_binary_record<W>(writer: &mut W, record: &[u8]) -> Result<()>
+where
+    W: Write + ?Sized,
+{
+    if record.len() > MAX_RECORD_SIZE {
+        bail!("Binary record too large");
+    }
+
+    writer.write_all(&(record.len() as u32).to_le_bytes())?;
+    writer.write_all(record)?;
+    Ok(())
+}
+
 /// File events factory retrieving and unmarshaling events
 /// parts.
 pub struct FileEventsFactory {
-    reader: BufReader<File>,
+    reader: Box<dyn BufRead + Send>,
     filetype: FileType,
+    format: FileFormat,
+    compression: FileCompression,
+    /// First line of a JSON file, consumed while detecting its type and not
+    /// yet returned.
+    first: Option<String>,
+    /// Buffer holding the last binary record.
+    record: Vec<u8>,
 }
 
 impl FileEventsFactory {
@@ -31,13 +134,49 @@ impl FileEventsFactory {
     where
         P: AsRef<Path>,
     {
-        let mut reader = BufReader::new(
+        Self::from_reader(
             File::open(&file)
                 .map_err(|e| anyhow!("Could not open {}: {e}", file.as_ref().display()))?,
-        );
-        let filetype = Self::detect_type(&mut reader)?;
+        )
+    }
+
+    /// Create a factory reading events from any reader, e.g. a socket. The
+    /// type, format and compression of the events are detected as for files.
+    /// Note that this blocks until the first event can be read.
+    pub fn from_reader<R>(reader: R) -> Result<Self>
+    where
+        R: Read + Send + 'static,
+    {
+        let mut reader = BufReader::new(reader);
+
+        let compression = FileCompression::detect(reader.fill_buf()?);
+        let mut reader: Box<dyn BufRead + Send> = match compression {
+            FileCompression::None => Box::new(reader),
+            FileCompression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+            FileCompression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        };
+
+        let (format, filetype, first) = match reader.fill_buf()?.first() {
+            None => bail!("File is empty"),
+            Some(b) if *b == BINARY_MAGIC[0] => (
+                FileFormat::Binary,
+                Self::detect_binary_type(&mut reader)?,
+                None,
+            ),
+            Some(_) => {
+                let (filetype, first) = Self::detect_type(&mut reader)?;
+                (FileFormat::Json, filetype, Some(first))
+            }
+        };
 
-        Ok(FileEventsFactory { reader, filetype })
+        Ok(FileEventsFactory {
+            reader,
+            filetype,
+            format,
+            compression,
+            first,
+            record: Vec::new(),
+        })
     }
 }
 
@@ -49,12 +188,10 @@ impl FileEventsFactory {
             FileType::Event => (),
             FileType::Series => bail!("Cannot read event from sorted file"),
         }
-        let mut line = String::new();
 
-        match self.reader.read_line(&mut line) {
-            Err(e) => Err(e.into()),
-            Ok(0) => Ok(None),
-            Ok(_) => Ok(Some(Event::from_json(line)?)),
+        match self.format {
+            FileFormat::Json => self.next_line()?.map(Event::from_json).transpose(),
+            FileFormat::Binary => self.next_record()?.map(Event::from_binary).transpose(),
         }
     }
 
@@ -65,18 +202,59 @@ impl FileEventsFactory {
             FileType::Event => bail!("Cannot read series from unsorted file"),
             FileType::Series => (),
         }
+
+        match self.format {
+            FileFormat::Json => self.next_line()?.map(EventSeries::from_json).transpose(),
+            FileFormat::Binary => self
+                .next_record()?
+                .map(EventSeries::from_binary)
+                .transpose(),
+        }
+    }
+
+    /// Retrieve the next line or None if we've reached the end of the file.
+    fn next_line(&mut self) -> Result<Option<String>> {
+        if let Some(line) = self.first.take() {
+            return Ok(Some(line));
+        }
+
         let mut line = String::new();
+        match self.reader.read_line(&mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line)),
+        }
+    }
 
-        match self.reader.read_line(&mut line) {
-            Err(e) => Err(e.into()),
-            Ok(0) => Ok(None),
-            Ok(_) => Ok(Some(EventSeries::from_json(line)?)),
+    /// Retrieve the next binary record or None if we've reached the end of the
+    /// file.
+    fn next_record(&mut self) -> Result<Option<&[u8]>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
         }
+
+        let mut len = [0; 4];
+        self.reader
+            .read_exact(&mut len)
+            .map_err(|e| anyhow!("Truncated binary record: {e}"))?;
+
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_RECORD_SIZE {
+            bail!("Binary record too large");
+        }
+
+        self.record.resize(len, 0);
+        self.reader
+            .read_exact(&mut self.record)
+            .map_err(|e| anyhow!("Truncated binary record: {e}"))?;
+
+        Ok(Some(&self.record))
     }
 
-    fn detect_type<T>(reader: &mut T) -> Result<FileType>
+    /// Detect the file type using its first line, which is returned so it can
+    /// be processed later on.
+    fn detect_type<T>(reader: &mut T) -> Result<(FileType, String)>
     where
-        T: BufRead + Seek,
+        T: BufRead,
     {
         let mut line = String::new();
 
@@ -85,26 +263,64 @@ impl FileEventsFactory {
             Ok(0) => return Err(anyhow!("File is empty")),
             Ok(_) => (),
         }
-        reader.rewind()?;
 
         let first: serde_json::Value = serde_json::from_str(line.as_str())
             .map_err(|e| anyhow!("Failed to parse event file: {:?}", e))?;
 
-        match first {
-            serde_json::Value::Object(_) => Ok(FileType::Event),
-            serde_json::Value::Array(_) => Ok(FileType::Series),
+        let filetype = match first {
+            serde_json::Value::Object(_) => FileType::Event,
+            serde_json::Value::Array(_) => FileType::Series,
             _ => bail!("File contains invalid json data"),
+        };
+
+        Ok((filetype, line))
+    }
+
+    /// Detect the file type of a binary file by parsing its header.
+    fn detect_binary_type<T>(reader: &mut T) -> Result<FileType>
+    where
+        T: BufRead,
+    {
+        let mut header = [0; BINARY_MAGIC.len() + 2];
+        reader
+            .read_exact(&mut header)
+            .map_err(|e| anyhow!("Failed to parse binary event file header: {e}"))?;
+
+        if header[..BINARY_MAGIC.len()] != BINARY_MAGIC {
+            bail!("Failed to parse event file: unknown format");
+        }
+
+        match header[BINARY_MAGIC.len()] {
+            BINARY_VERSION => (),
+            x => bail!("Unsupported binary event file version ({x})"),
         }
+
+        Ok(match header[BINARY_MAGIC.len() + 1] {
+            0 => FileType::Event,
+            1 => FileType::Series,
+            x => bail!("Invalid binary event file type ({x})"),
+        })
     }
 
     pub fn file_type(&self) -> &FileType {
         &self.filetype
     }
+
+    pub fn format(&self) -> FileFormat {
+        self.format
+    }
+
+    pub fn compression(&self) -> FileCompression {
+        self.compression
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{env, fs, io};
+
     use super::*;
+
     #[test]
     fn read_from_file() {
         let mut fact = FileEventsFactory::new("test_data/test_events.json").unwrap();
@@ -116,4 +332,118 @@ mod tests {
         }
         assert!(events.len() == 4);
     }
+
+    #[test]
+    fn read_from_compressed_file() {
+        for compression in [FileCompression::Gzip, FileCompression::Zstd] {
+            let path = env::temp_dir().join(format!(
+                "retis-events-{:?}-{}.json",
+                compression,
+                std::process::id()
+            ));
+
+            {
+                let mut writer = compression.writer(File::create(&path).unwrap()).unwrap();
+                io::copy(
+                    &mut File::open("test_data/test_events.json").unwrap(),
+                    &mut writer,
+                )
+                .unwrap();
+            }
+
+            let mut fact = FileEventsFactory::new(&path).unwrap();
+            assert_eq!(fact.compression(), compression);
+
+            let mut events = 0;
+            while fact.next_event().unwrap().is_some() {
+                events += 1;
+            }
+            assert!(events == 4);
+
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let path = env::temp_dir().join(format!("retis-events-bin-{}", std::process::id()));
+
+        let mut fact = FileEventsFactory::new("test_data/test_events.json").unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = fact.next_event().unwrap() {
+            events.push(event);
+        }
+
+        {
+            let mut file = File::create(&path).unwrap();
+            let mut buf = Vec::new();
+            write_binary_header(&mut file, &FileType::Event).unwrap();
+            for event in events.iter() {
+                buf.clear();
+                event.to_binary(&mut buf).unwrap();
+                write_binary_record(&mut file, &buf).unwrap();
+            }
+        }
+
+        let mut fact = FileEventsFactory::new(&path).unwrap();
+        assert_eq!(fact.format(), FileFormat::Binary);
+        assert!(matches!(fact.file_type(), FileType::Event));
+
+        for event in events.iter() {
+            let read = fact.next_event().unwrap().unwrap();
+            assert_eq!(read.to_json(), event.to_json());
+        }
+        assert!(fact.next_event().unwrap().is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn binary_record_too_large() {
+        let path = env::temp_dir().join(format!("retis-events-large-{}", std::process::id()));
+
+        {
+            let mut file = File::create(&path).unwrap();
+            write_binary_header(&mut file, &FileType::Event).unwrap();
+            file.write_all(&u32::MAX.to_le_bytes()).unwrap();
+        }
+
+        let mut fact = FileEventsFactory::new(&path).unwrap();
+        assert!(fact.next_event().is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn binary_series_round_trip() {
+        let path = env::temp_dir().join(format!("retis-series-bin-{}", std::process::id()));
+
+        let mut fact = FileEventsFactory::new("test_data/test_events_sorted.json").unwrap();
+        let mut series = Vec::new();
+        while let Some(s) = fact.next_series().unwrap() {
+            series.push(s);
+        }
+
+        {
+            let mut file = File::create(&path).unwrap();
+            let mut buf = Vec::new();
+            write_binary_header(&mut file, &FileType::Series).unwrap();
+            for s in series.iter() {
+                buf.clear();
+                s.to_binary(&mut buf).unwrap();
+                write_binary_record(&mut file, &buf).unwrap();
+            }
+        }
+
+        let mut fact = FileEventsFactory::new(&path).unwrap();
+        assert!(matches!(fact.file_type(), FileType::Series));
+
+        for s in series.iter() {
+            let read = fact.next_series().unwrap().unwrap();
+            assert_eq!(read.to_json(), s.to_json());
+        }
+        assert!(fact.next_series().unwrap().is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
 }
@@ -12,6 +12,8 @@ pub use events::*;
 pub mod display;
 pub use display::*;
 
+pub mod field;
+pub use field::*;
 pub mod file;
 pub mod helpers;
 #[cfg(feature = "python")]
@@ -25,6 +27,8 @@ pub mod ct;
 pub use ct::*;
 pub mod kernel;
 pub use kernel::*;
+pub mod lost;
+pub use lost::*;
 pub mod nft;
 pub use nft::*;
 pub mod ovs;
@@ -54,5 +58,6 @@ fn retis(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<python::PyEventReader>()?;
     m.add_class::<python::PySeriesReader>()?;
     m.add_class::<python::PyEventFile>()?;
+    m.add_class::<python::PyEventWriter>()?;
     Ok(())
 }
@@ -0,0 +1,52 @@
+use std::fmt;
+
+use crate::*;
+
+/// Lost events section. Reports events a probe could not send to user space
+/// (e.g. because the events buffer was full) during a time window. The window
+/// ends at the time the section was generated, which is the timestamp of the
+/// common section.
+#[event_section(SectionId::Lost)]
+pub struct LostEvent {
+    /// Probe which lost events: a kernel symbol or a USDT target.
+    pub probe: String,
+    /// Number of events lost during the time window.
+    pub count: u64,
+    /// Start of the time window.
+    pub window_start: u64,
+    /// End of the time window.
+    pub window_end: u64,
+}
+
+impl EventFmt for LostEvent {
+    fn event_fmt(&self, f: &mut Formatter, _: &DisplayFormat) -> fmt::Result {
+        write!(
+            f,
+            "lost {} event(s) from {} in the last {}ms",
+            self.count,
+            self.probe,
+            self.window_end.saturating_sub(self.window_start) / 1_000_000
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lost_event() -> anyhow::Result<()> {
+        let event = Event::from_json(
+            r#"{"common":{"timestamp":3000000000},"lost":{"probe":"kfree_skb_reason","count":42,"window_start":2000000000,"window_end":3000000000}}"#.to_string(),
+        )?;
+
+        let lost = event.get_section::<LostEvent>(SectionId::Lost).unwrap();
+        assert_eq!(lost.count, 42);
+        assert_eq!(
+            lost.display(&DisplayFormat::new(), &FormatterConf::new())
+                .to_string(),
+            "lost 42 event(s) from kfree_skb_reason in the last 1000ms"
+        );
+        Ok(())
+    }
+}
@@ -3,12 +3,18 @@
 //! This module contains python bindings for retis events so that they can
 //! be inspected in post-processing tools written in python.
 
-use std::{collections::HashMap, path::PathBuf, str::FromStr};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    str::FromStr,
+};
 
 use pyo3::{
-    exceptions::{PyKeyError, PyRuntimeError},
+    exceptions::{PyKeyError, PyRuntimeError, PyTypeError, PyValueError},
     prelude::*,
-    types::PyList,
+    types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple},
 };
 
 use super::*;
@@ -53,6 +59,20 @@ use super::*;
 /// 633902702662502 (8) [scapy] 2856768 [tp] net:net_dev_queue #24087f96a1366ffff8fa9b9718500 (skb ffff8fa94fabd500)
 ///   if 15 (p1_p) 2001:db8:dead::1.20 > 2001:db8:dead::2.80 ttl 64 len 20 proto TCP (6) flags [S] seq 0 win 8192
 /// ```
+///
+/// # Creating events
+///
+/// Events can be created from a dictionary following the same layout as the
+/// one returned by `raw()`. This allows modifying (or annotating) events and
+/// writing them back to a file, see `EventWriter`.
+///
+/// ## Examples
+///
+/// ```text
+/// >>> raw = event.raw()
+/// >>> raw["skb"]["tcp"]["dport"] = 8081
+/// >>> event = Event(raw)
+/// ```
 #[pyclass(name = "Event")]
 pub struct PyEvent(Event);
 
@@ -64,6 +84,17 @@ impl PyEvent {
 
 #[pymethods]
 impl PyEvent {
+    #[new]
+    fn py_new(raw: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let obj = raw
+            .iter()
+            .map(|(k, v)| Ok((k.extract::<String>()?, from_pyobject(&v)?)))
+            .collect::<PyResult<HashMap<_, _>>>()?;
+        Ok(Self(
+            Event::from_json_obj(obj).map_err(|e| PyValueError::new_err(e.to_string()))?,
+        ))
+    }
+
     /// Controls how the PyEvent is represented, eg. what is the output of
     /// `print(e)`.
     fn __repr__<'a>(&'a self, py: Python<'a>) -> String {
@@ -100,6 +131,19 @@ impl PyEvent {
         to_pyobject(&self.0.to_json(), py)
     }
 
+    /// Returns the value of a field given its path, or None if the field is
+    /// not set in the event.
+    ///
+    /// Paths start with the section name, followed by the (nested) field
+    /// names, eg. `e.get_field('skb.tcp.dport')`.
+    fn get_field(&self, py: Python<'_>, path: &str) -> PyResult<PyObject> {
+        match self.0.get_field(path) {
+            Ok(Some(value)) => Ok(to_pyobject(&value.to_json(), py)),
+            Ok(None) => Ok(py.None()),
+            Err(e) => Err(PyKeyError::new_err(e.to_string())),
+        }
+    }
+
     /// Returns a string representation of the event
     fn show(&self) -> String {
         let format = crate::DisplayFormat::new().multiline(true);
@@ -359,6 +403,198 @@ impl PyEventFile {
     pub(crate) fn series(&self) -> PyResult<PySeriesReader> {
         PySeriesReader::new(self.path.clone())
     }
+
+    /// Writes the events (or series, for sorted files) for which the provided
+    /// function returns True to a new file. Returns the number of events (or
+    /// series) written.
+    pub(crate) fn filter(
+        &self,
+        py: Python<'_>,
+        func: &Bound<'_, PyAny>,
+        out: PathBuf,
+    ) -> PyResult<usize> {
+        let mut factory = file::FileEventsFactory::new(&self.path)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let mut writer = PyEventWriter::new(out)?;
+        let mut written = 0;
+
+        loop {
+            let obj = match self.ftype {
+                file::FileType::Event => match factory
+                    .next_event()
+                    .map_err(|e| PyRuntimeError::new_err(e.to_string()))?
+                {
+                    Some(event) => Bound::new(py, PyEvent::new(event))?.into_any(),
+                    None => break,
+                },
+                file::FileType::Series => match factory
+                    .next_series()
+                    .map_err(|e| PyRuntimeError::new_err(e.to_string()))?
+                {
+                    Some(series) => Bound::new(py, PyEventSeries::new(py, series)?)?.into_any(),
+                    None => break,
+                },
+            };
+
+            if func.call1((&obj,))?.is_truthy()? {
+                writer.write(py, &obj)?;
+                written += 1;
+            }
+        }
+
+        writer.close()?;
+        Ok(written)
+    }
+}
+
+/// Python event writer
+///
+/// Objects of this class can write events (or series) to a file, in the JSON
+/// format. Files written this way can be used as an input of the retis
+/// post-processing commands. A file can only hold events or series, the first
+/// written object defining which.
+///
+/// ## Example
+///
+/// ```python
+/// writer = EventWriter("filtered.data")
+///
+/// for event in EventReader("retis.data"):
+///     if "skb_drop" in event:
+///         writer.write(event)
+///
+/// writer.close()
+/// ```
+#[pyclass(name = "EventWriter", unsendable)]
+pub(crate) struct PyEventWriter {
+    writer: Option<BufWriter<File>>,
+    /// Type of the objects written to the file, known after the first write.
+    ftype: Option<file::FileType>,
+}
+
+#[pymethods]
+impl PyEventWriter {
+    #[new]
+    pub(crate) fn new(path: PathBuf) -> PyResult<Self> {
+        let file = File::create(&path).map_err(|e| {
+            PyRuntimeError::new_err(format!("Could not create '{}': {e}", path.display()))
+        })?;
+
+        Ok(PyEventWriter {
+            writer: Some(BufWriter::new(file)),
+            ftype: None,
+        })
+    }
+
+    /// Writes an Event or an EventSeries to the file.
+    pub(crate) fn write(&mut self, py: Python<'_>, obj: &Bound<'_, PyAny>) -> PyResult<()> {
+        let (ftype, json) = if let Ok(event) = obj.downcast::<PyEvent>() {
+            (file::FileType::Event, event.borrow().0.to_json())
+        } else if let Ok(series) = obj.downcast::<PyEventSeries>() {
+            let events = series
+                .borrow()
+                .events
+                .iter()
+                .map(|e| e.borrow(py).0.to_json())
+                .collect();
+            (file::FileType::Series, serde_json::Value::Array(events))
+        } else {
+            return Err(PyTypeError::new_err("Expected an Event or an EventSeries"));
+        };
+
+        match (&self.ftype, &ftype) {
+            (None, _) => self.ftype = Some(ftype),
+            (Some(file::FileType::Event), file::FileType::Event)
+            | (Some(file::FileType::Series), file::FileType::Series) => (),
+            _ => {
+                return Err(PyTypeError::new_err(
+                    "Cannot write both events and series to the same file",
+                ))
+            }
+        }
+
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| PyRuntimeError::new_err("Writer is closed"))?;
+        let mut line =
+            serde_json::to_vec(&json).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        line.push(b'\n');
+        writer.write_all(&line)?;
+        Ok(())
+    }
+
+    /// Flushes the written events to the file.
+    pub(crate) fn flush(&mut self) -> PyResult<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the written events and closes the file. Further writes will
+    /// fail.
+    pub(crate) fn close(&mut self) -> PyResult<()> {
+        self.flush()?;
+        self.writer = None;
+        Ok(())
+    }
+
+    // Implementation of the context manager protocol.
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: &Bound<'_, PyAny>,
+        _exc_value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) -> PyResult<()> {
+        self.close()
+    }
+}
+
+/// Converts a PyObject to a serde_json::Value.
+pub(crate) fn from_pyobject(obj: &Bound<'_, PyAny>) -> PyResult<serde_json::Value> {
+    use serde_json::Value;
+    Ok(if obj.is_none() {
+        Value::Null
+    } else if let Ok(b) = obj.downcast::<PyBool>() {
+        Value::Bool(b.is_true())
+    } else if obj.is_instance_of::<PyInt>() {
+        match obj.extract::<i64>() {
+            Ok(i) => Value::from(i),
+            Err(_) => Value::from(obj.extract::<u64>()?),
+        }
+    } else if let Ok(f) = obj.downcast::<PyFloat>() {
+        Value::from(f.value())
+    } else if let Ok(s) = obj.downcast::<PyString>() {
+        Value::String(s.to_cow()?.into_owned())
+    } else if let Ok(l) = obj.downcast::<PyList>() {
+        Value::Array(
+            l.iter()
+                .map(|x| from_pyobject(&x))
+                .collect::<PyResult<_>>()?,
+        )
+    } else if let Ok(t) = obj.downcast::<PyTuple>() {
+        Value::Array(
+            t.iter()
+                .map(|x| from_pyobject(&x))
+                .collect::<PyResult<_>>()?,
+        )
+    } else if let Ok(d) = obj.downcast::<PyDict>() {
+        Value::Object(
+            d.iter()
+                .map(|(k, v)| Ok((k.extract::<String>()?, from_pyobject(&v)?)))
+                .collect::<PyResult<_>>()?,
+        )
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "Cannot convert {} to an event value",
+            obj.get_type().name()?
+        )));
+    })
 }
 
 /// Converts a serde_json::Value to a PyObject.
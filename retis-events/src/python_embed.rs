@@ -32,6 +32,9 @@ impl<'a> PyShell<'a> {
     fn new(py: Python<'a>, file: PyEventFile) -> PyResult<Self> {
         let globals = PyDict::new_bound(py);
         globals.set_item("reader", Py::new(py, file)?.into_bound(py))?;
+        // Allow scripts to create and write events.
+        globals.set_item("Event", py.get_type_bound::<PyEvent>())?;
+        globals.set_item("EventWriter", py.get_type_bound::<PyEventWriter>())?;
 
         Ok(Self { py, globals })
     }
@@ -7,7 +7,7 @@ use crate::{
     process::{display::*, series::EventSorter, tracking::AddTracking},
 };
 
-/// Benchmark time to output events (text, json).
+/// Benchmark time to output events (text, json, binary).
 pub(super) fn bench(ci: bool) -> Result<()> {
     let iters = match ci {
         false => 1000000,
@@ -55,6 +55,16 @@ pub(super) fn bench(ci: bool) -> Result<()> {
     }
     println!("1M_print_single_json_us {}", now.elapsed().as_micros());
 
+    let mut p = PrintEvent::new(
+        Box::new(OpenOptions::new().write(true).open("/dev/null")?),
+        PrintEventFormat::Binary,
+    );
+    let now = Instant::now();
+    for _ in 0..iters {
+        p.process_one(&event)?;
+    }
+    println!("1M_print_single_binary_us {}", now.elapsed().as_micros());
+
     // PrintSeries benchmark
 
     let mut factory = FileEventsFactory::new("retis/test_data/test_events_bench.json")?;
@@ -100,5 +110,15 @@ pub(super) fn bench(ci: bool) -> Result<()> {
     }
     println!("1M_print_series_json_us {}", now.elapsed().as_micros());
 
+    let mut p = PrintSeries::new(
+        Box::new(OpenOptions::new().write(true).open("/dev/null")?),
+        PrintEventFormat::Binary,
+    );
+    let now = Instant::now();
+    for _ in 0..iters {
+        p.process_one(&series)?;
+    }
+    println!("1M_print_series_binary_us {}", now.elapsed().as_micros());
+
     Ok(())
 }
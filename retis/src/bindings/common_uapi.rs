@@ -30,3 +30,16 @@ pub struct retis_probe_config {
     pub offsets: retis_probe_offsets,
     pub stack_trace: u8_,
 }
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct retis_sampling_state {
+    pub seen: u64_,
+    pub window_start: u64_,
+    pub window_count: u64_,
+}
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct retis_sampling_flow {
+    pub timestamp: u64_,
+    pub orig_head: u64_,
+}
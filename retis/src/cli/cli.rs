@@ -18,8 +18,11 @@ use super::dynamic::DynamicCommand;
 #[cfg(feature = "benchmark")]
 use crate::benchmark::cli::Benchmark;
 use crate::{
-    collect::cli::Collect,
-    events::SectionId,
+    collect::{cli::Collect, control::Ctl},
+    events::{
+        file::{FileCompression, FileFormat},
+        SectionId,
+    },
     generate::Complete,
     inspect::Inspect,
     module::Modules,
@@ -421,12 +424,59 @@ pub(crate) enum CliDisplayFormat {
     MultiLine,
 }
 
+/// Type of the "out-format" arguments.
+// It is an enum that maps 1:1 with the file formats defined in events library.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub(crate) enum CliFileFormat {
+    #[default]
+    Json,
+    Binary,
+}
+
+impl From<CliFileFormat> for FileFormat {
+    fn from(format: CliFileFormat) -> Self {
+        match format {
+            CliFileFormat::Json => FileFormat::Json,
+            CliFileFormat::Binary => FileFormat::Binary,
+        }
+    }
+}
+
+/// Type of the "compression" arguments.
+// It is an enum that maps 1:1 with the compression algorithms defined in events library.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub(crate) enum CliCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl From<CliCompression> for FileCompression {
+    fn from(compression: CliCompression) -> Self {
+        match compression {
+            CliCompression::None => FileCompression::None,
+            CliCompression::Gzip => FileCompression::Gzip,
+            CliCompression::Zstd => FileCompression::Zstd,
+        }
+    }
+}
+
 /// Create and register a ThinCli
 pub(crate) fn get_cli() -> Result<ThinCli> {
     let mut cli = ThinCli::new()?;
     cli.add_subcommand(Box::new(Collect::new()?))?;
     cli.add_subcommand(Box::new(Print::new()?))?;
     cli.add_subcommand(Box::new(Sort::new()?))?;
+    cli.add_subcommand(Box::new(Stats::new()?))?;
+    cli.add_subcommand(Box::new(Latency::new()?))?;
+    cli.add_subcommand(Box::new(Graph::new()?))?;
+    cli.add_subcommand(Box::new(Export::new()?))?;
+    cli.add_subcommand(Box::new(FilterCmd::new()?))?;
+    cli.add_subcommand(Box::new(Convert::new()?))?;
+    cli.add_subcommand(Box::new(Merge::new()?))?;
+    cli.add_subcommand(Box::new(Receive::new()?))?;
+    cli.add_subcommand(Box::new(Ctl::new()?))?;
     #[cfg(feature = "python")]
     cli.add_subcommand(Box::new(PythonCli::new()?))?;
     cli.add_subcommand(Box::new(Pcap::new()?))?;
@@ -2,16 +2,20 @@
 //!
 //! Collect is a dynamic CLI subcommand that allows collectors to register their arguments.
 
-use std::{any::Any, collections::HashSet, path::PathBuf};
+use std::{any::Any, collections::HashSet, path::PathBuf, time::Duration};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::{
     error::Error as ClapError,
     {builder::PossibleValuesParser, error::ErrorKind, Arg, ArgAction, ArgMatches, Args, Command},
 };
 
-use super::CollectRunner;
-use crate::cli::{dynamic::DynamicCommand, SubCommand, *};
+use super::{control::DEFAULT_CONTROL_SOCKET, recorder::RecorderLimit, CollectRunner};
+use crate::{
+    cli::{dynamic::DynamicCommand, SubCommand, *},
+    helpers::{size::parse_size, socket::SocketAddr, time::parse_duration},
+    process::filter::EventFilter,
+};
 
 #[derive(Args, Debug, Default)]
 pub(crate) struct CollectArgs {
@@ -27,6 +31,93 @@ not released. If exhausted, no stack trace will be included."
         help = "Execute a command and terminate the collection once done."
     )]
     pub(super) cmd: Option<String>,
+    #[arg(
+        long,
+        value_parser = parse_duration,
+        help = "Stop the collection after the given duration. Supported units are ms, s (default),
+m, h and d.
+
+Example: --duration 30s"
+    )]
+    pub(super) duration: Option<Duration>,
+    #[arg(long, help = "Stop the collection after the given number of events.")]
+    pub(super) max_events: Option<u64>,
+    #[arg(
+        long,
+        help = r#"Stop the collection once an event matching the given trigger is seen (see
+--stop-delay). Triggers are filter expressions, using the same syntax as the post-processing
+--filter option. The simplest ones follow the SECTION[.FIELD...][(==|!=)VALUE] format: without
+a comparison, events match if they contain the given section (and field).
+
+Examples:
+  --stop-on skb-drop
+  --stop-on 'skb-drop.drop_reason == NETFILTER_DROP && kernel.symbol =~ "kfree_skb*"'"#
+    )]
+    pub(super) stop_on: Option<EventFilter>,
+    #[arg(
+        long,
+        requires = "stop_on",
+        value_parser = parse_duration,
+        default_value = "0",
+        help = "Delay between the --stop-on trigger matching and the collection being stopped."
+    )]
+    pub(super) stop_delay: Duration,
+    #[arg(
+        long,
+        requires = "flight_recorder_trigger",
+        help = "Flight recorder mode: keep the latest events in a bounded in-memory buffer and only
+output them once an event matching --flight-recorder-trigger is seen, followed by the events
+of a post-trigger window (see --flight-recorder-post). The buffer is bounded by a duration
+(ms, s (default), m, h, d) or by a size (K, M, G).
+
+Examples:
+  --flight-recorder 2s
+  --flight-recorder 100M"
+    )]
+    pub(super) flight_recorder: Option<RecorderLimit>,
+    #[arg(
+        long,
+        requires = "flight_recorder",
+        help = r#"Trigger of the flight recorder, following the same format as --stop-on.
+
+Example: --flight-recorder-trigger "skb-drop.drop_reason == NETFILTER_DROP""#
+    )]
+    pub(super) flight_recorder_trigger: Option<EventFilter>,
+    #[arg(
+        long,
+        requires = "flight_recorder",
+        value_parser = parse_duration,
+        default_value = "0",
+        help = "Duration during which events are output after the flight recorder was triggered.
+Events matching the trigger during this window extend it."
+    )]
+    pub(super) flight_recorder_post: Duration,
+    #[arg(
+        long,
+        value_parser = parse_sample_rate,
+        help = "Only report one event out of N, per probe (or per packet, see --sample-per-flow).
+Sampling is done in the kernel and applies to events linked to packets matching the filters.
+The sampling parameters are recorded in the events so statistics can be rescaled.
+
+Example: --sample 1/100"
+    )]
+    pub(super) sample: Option<u32>,
+    #[arg(
+        long,
+        value_parser = parse_rate_limit,
+        help = "Report at most N events per second, per probe (or per packet, see
+--sample-per-flow). Rate limiting is done in the kernel and applies to events linked to
+packets matching the filters. It can be combined with --sample.
+
+Example: --rate-limit 10000/s"
+    )]
+    pub(super) rate_limit: Option<u32>,
+    #[arg(
+        long,
+        help = "Apply --sample and --rate-limit per packet (skb tracking id) rather than per
+probe, so the events of a given packet are either all reported or all dropped."
+    )]
+    pub(super) sample_per_flow: bool,
     // Some of the options that we want for this arg are not available in clap's derive interface
     // so both the argument definition and the field population will be done manually.
     #[arg(skip)]
@@ -66,7 +157,93 @@ defaults to \"retis.data\"."
     pub(super) out: Option<PathBuf>,
     #[arg(
         long,
-        help = "Write the events to stdout even if --out is used.",
+        requires = "out",
+        value_enum,
+        default_value_t = CliFileFormat::Json,
+        help = "Format of the events written to the output file. The binary format is more compact
+and faster to write and read, and can be converted back to JSON using 'retis convert'."
+    )]
+    pub(super) out_format: CliFileFormat,
+    #[arg(
+        long,
+        requires = "out",
+        value_enum,
+        default_value_t = CliCompression::None,
+        help = "Compress the events written to the output file."
+    )]
+    pub(super) out_compression: CliCompression,
+    #[arg(
+        long,
+        requires = "out",
+        value_parser = parse_size,
+        help = "Rotate the output file once it reaches the given size (before compression, if
+any). Supported units are B (default), K, M and G (powers of 1024). Rotated files have an
+increasing index appended to their name (e.g. retis.data.1, retis.data.2, ...).
+
+Example: --out-max-size 100M"
+    )]
+    pub(super) out_max_size: Option<u64>,
+    #[arg(
+        long,
+        requires = "out",
+        value_parser = parse_duration,
+        help = "Rotate the output file once it covers the given duration. Supported units are
+ms, s (default), m, h and d.
+
+Example: --out-max-duration 1h"
+    )]
+    pub(super) out_max_duration: Option<Duration>,
+    #[arg(
+        long,
+        requires = "out",
+        help = "Maximum number of output files to keep when rotation is used. Older files are
+removed. Requires --out-max-size and/or --out-max-duration."
+    )]
+    pub(super) out_max_files: Option<usize>,
+    #[arg(
+        long,
+        help = "Stream the events to a socket, in the JSON format (one event per line). The socket
+must be listening when the collection starts, see 'retis receive'. Valid addresses are
+unix:PATH and tcp:HOST:PORT.
+
+Example: --out-socket unix:/run/retis.sock"
+    )]
+    pub(super) out_socket: Option<SocketAddr>,
+    #[arg(
+        long,
+        help = "Write the packets to a PCAP-ng file (or FIFO) while collecting events. Packets are
+written as soon as they are collected, so tools such as Wireshark can be used on the file
+while it grows. This requires the raw packet to be collected, see the 'pcap' profile."
+    )]
+    pub(super) out_pcap: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "out_pcap",
+        help = "Only write to the PCAP-ng output packets from this probe. Can be used multiple
+times. Probes should follow the [TYPE:]TARGET pattern, see --probe. By default packets from
+all probes are written."
+    )]
+    pub(super) pcap_probe: Vec<String>,
+    #[arg(
+        long,
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_CONTROL_SOCKET,
+        help = "Listen for control requests on a UNIX socket while collecting events, see
+'retis ctl'. If the flag is used without a path, defaults to \"retis.sock\"."
+    )]
+    pub(super) control_socket: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "HOST:PORT",
+        help = "Serve metrics aggregated from the collected events (events per probe, drops,
+nft verdicts, conntrack states and lost events) over HTTP in the Prometheus text format.
+
+Example: --metrics-listen 127.0.0.1:9090"
+    )]
+    pub(super) metrics_listen: Option<std::net::SocketAddr>,
+    #[arg(
+        long,
+        help = "Write the events to stdout even if --out, --out-socket or --out-pcap is used.",
         default_value = "false"
     )]
     pub(super) print: bool,
@@ -142,6 +319,24 @@ fully operational:
     pub(crate) allow_system_changes: bool,
 }
 
+/// Parse a sampling rate following the 1/N (or N) format.
+fn parse_sample_rate(s: &str) -> Result<u32> {
+    let s = s.trim();
+    match s.strip_prefix("1/").unwrap_or(s).trim().parse::<u32>() {
+        Ok(0) | Err(_) => bail!("Invalid sampling rate '{s}', expected 1/N with N > 0"),
+        Ok(n) => Ok(n),
+    }
+}
+
+/// Parse a rate limit following the N/s (or N) format.
+fn parse_rate_limit(s: &str) -> Result<u32> {
+    let s = s.trim();
+    match s.strip_suffix("/s").unwrap_or(s).trim().parse::<u32>() {
+        Ok(0) | Err(_) => bail!("Invalid rate limit '{s}', expected N/s with N > 0"),
+        Ok(n) => Ok(n),
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Collect {
     args: CollectArgs,
@@ -257,3 +452,21 @@ impl Collect {
         Ok(&self.args)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sampling() {
+        assert_eq!(parse_sample_rate("1/100").unwrap(), 100);
+        assert_eq!(parse_sample_rate("42").unwrap(), 42);
+        assert!(parse_sample_rate("1/0").is_err());
+        assert!(parse_sample_rate("2/3").is_err());
+
+        assert_eq!(parse_rate_limit("10000/s").unwrap(), 10000);
+        assert_eq!(parse_rate_limit("5").unwrap(), 5);
+        assert!(parse_rate_limit("0/s").is_err());
+        assert!(parse_rate_limit("10/m").is_err());
+    }
+}
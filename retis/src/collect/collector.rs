@@ -7,14 +7,21 @@ use std::{
     process::{Command, Stdio},
     str::FromStr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, bail, Result};
 use log::{debug, info, warn};
 use nix::{errno::Errno, mount::*, unistd::Uid};
 
-use super::cli::Collect;
+use super::{
+    cli::{Collect, CollectArgs},
+    control::{ControlRequest, ControlSocket, ControlState},
+    metrics::MetricsServer,
+    output::{EventsFile, EventsSocket, RotationConfig},
+    recorder::FlightRecorder,
+    stop::StopConditions,
+};
 use crate::{
     bindings::packet_filter_uapi,
     cli::{dynamic::DynamicCommand, CliConfig, CliDisplayFormat, FullCli, SubCommandRunner},
@@ -28,7 +35,10 @@ use crate::{
         inspect::check::collection_prerequisites,
         kernel::Symbol,
         probe::{
-            kernel::{probe_stack::ProbeStack, utils::probe_from_cli},
+            kernel::{
+                probe_stack::ProbeStack,
+                utils::{parse_cli_probe, probe_from_cli},
+            },
             *,
         },
         tracking::{gc::TrackingGC, skb_tracking::init_tracking},
@@ -36,7 +46,7 @@ use crate::{
     events::*,
     helpers::{signals::Running, time::*},
     module::{ModuleId, Modules},
-    process::display::*,
+    process::{cli::PcapOutput, display::*},
 };
 
 #[cfg(not(test))]
@@ -45,6 +55,9 @@ use crate::core::{
     probe::kernel::{config::init_stack_map, kernel::KernelEventFactory},
 };
 
+/// Interval at which lost events are reported while collecting.
+const LOST_EVENTS_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Generic trait representing a collector. All collectors are required to
 /// implement this, as they'll be manipulated through this trait.
 pub(crate) trait Collector {
@@ -191,6 +204,25 @@ impl Collectors {
             bail!("Probe-stack mode requires filtering (--filter-packet and/or --filter-meta)");
         }
 
+        if let Some(max_files) = collect.out_max_files {
+            if max_files == 0 {
+                bail!("--out-max-files must be greater than 0");
+            }
+            if collect.out_max_size.is_none() && collect.out_max_duration.is_none() {
+                bail!("--out-max-files requires --out-max-size and/or --out-max-duration");
+            }
+        }
+
+        if collect.sample_per_flow && collect.sample.is_none() && collect.rate_limit.is_none() {
+            bail!("--sample-per-flow requires --sample and/or --rate-limit");
+        }
+
+        // Validate the PCAP-ng probe filters early.
+        collect
+            .pcap_probe
+            .iter()
+            .try_for_each(|probe| parse_cli_probe(probe).map(|_| ()))?;
+
         // --allow-system-changes requires root.
         if collect.allow_system_changes && !Uid::effective().is_root() {
             bail!("Retis needs to be run as root when --allow-system-changes is used");
@@ -242,6 +274,24 @@ impl Collectors {
                 .set_probe_opt(probe::ProbeOption::StackTrace)?;
         }
 
+        // Configure in-kernel sampling & rate limiting, if asked to.
+        let args = collect.args()?;
+        let sampling = match (args.sample, args.rate_limit) {
+            (None, None) => None,
+            (sample_rate, rate_limit) => {
+                self.probes.builder_mut()?.set_sampling(
+                    sample_rate.unwrap_or(0),
+                    rate_limit.unwrap_or(0),
+                    args.sample_per_flow,
+                );
+                Some(SamplingConfig {
+                    sample_rate,
+                    rate_limit,
+                    per_flow: args.sample_per_flow,
+                })
+            }
+        };
+
         // Generate an initial event with the startup section.
         self.events_factory.add_event(|event| {
             event.insert_section(
@@ -251,6 +301,7 @@ impl Collectors {
                         .unwrap_or("unspec")
                         .to_string(),
                     clock_monotonic_offset: monotonic_clock_offset()?,
+                    sampling: sampling.clone(),
                 }),
             )
         })?;
@@ -468,11 +519,13 @@ impl Collectors {
             .ok_or_else(|| anyhow!("wrong subcommand"))?
             .args()?;
 
-        let mut printers = Vec::new();
+        let mut printers: Vec<Box<dyn EventOutput>> = Vec::new();
 
-        // Write events to stdout if we don't write to a file (--out) or if
-        // explicitly asked to (--print).
-        if collect.out.is_none() || collect.print {
+        // Write events to stdout if we don't write them elsewhere (--out,
+        // --out-socket or --out-pcap), or if explicitly asked to (--print).
+        if (collect.out.is_none() && collect.out_socket.is_none() && collect.out_pcap.is_none())
+            || collect.print
+        {
             let format = DisplayFormat::new()
                 .multiline(collect.format == CliDisplayFormat::MultiLine)
                 .time_format(if collect.utc {
@@ -482,25 +535,43 @@ impl Collectors {
                 })
                 .monotonic_offset(monotonic_clock_offset()?);
 
-            printers.push(PrintEvent::new(
+            printers.push(Box::new(PrintEvent::new(
                 Box::new(io::stdout()),
                 PrintEventFormat::Text(format),
-            ));
+            )));
         }
 
         // Write the events to a file if asked to.
         if let Some(out) = collect.out.as_ref() {
-            printers.push(PrintEvent::new(
-                Box::new(BufWriter::new(
-                    OpenOptions::new()
-                        .create(true)
-                        .write(true)
-                        .truncate(true)
-                        .open(out)
-                        .or_else(|_| bail!("Could not create or open '{}'", out.display()))?,
-                )),
-                PrintEventFormat::Json,
-            ));
+            printers.push(Box::new(EventsFile::new(
+                out,
+                RotationConfig {
+                    max_size: collect.out_max_size,
+                    max_duration: collect.out_max_duration,
+                    max_files: collect.out_max_files,
+                },
+                collect.out_format.into(),
+                collect.out_compression.into(),
+            )?));
+        }
+
+        // Stream the events to a socket if asked to.
+        if let Some(addr) = collect.out_socket.as_ref() {
+            printers.push(Box::new(EventsSocket::new(addr)?));
+        }
+
+        // Write packets to a PCAP-ng file if asked to.
+        if let Some(out) = collect.out_pcap.as_ref() {
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(out)
+                .or_else(|_| bail!("Could not create or open '{}'", out.display()))?;
+            printers.push(Box::new(PcapOutput::new(
+                BufWriter::new(file),
+                &collect.pcap_probe,
+            )?));
         }
 
         if let Some(cmd) = collect.cmd.to_owned() {
@@ -529,8 +600,52 @@ impl Collectors {
             self.known_kernel_types.clone(),
         );
 
+        let mut stop = StopConditions::new(
+            collect.duration,
+            collect.max_events,
+            collect.stop_on.clone(),
+            collect.stop_delay,
+        );
+
+        let mut recorder = match (
+            collect.flight_recorder,
+            collect.flight_recorder_trigger.as_ref(),
+        ) {
+            (Some(limit), Some(trigger)) => Some(FlightRecorder::new(
+                limit,
+                trigger.clone(),
+                collect.flight_recorder_post,
+            )),
+            _ => None,
+        };
+
+        let control = match &collect.control_socket {
+            Some(path) => {
+                info!("Listening for control requests on {}", path.display());
+                Some(ControlSocket::new(path.clone())?)
+            }
+            None => None,
+        };
+        let mut state = ControlState::default();
+
+        let metrics_server = match &collect.metrics_listen {
+            Some(addr) => {
+                info!("Serving metrics on http://{addr}/metrics");
+                Some(MetricsServer::new(addr)?)
+            }
+            None => None,
+        };
+
+        let mut lost_report = Instant::now();
+
         use EventResult::*;
         while self.run.running() {
+            if stop.expired() {
+                info!("Stop condition reached, terminating ...");
+                self.run.terminate();
+                break;
+            }
+
             // First always try to dequeue all Retis events. This is not a
             // blocking call.
             while let Some(event) = self.events_factory.next_event() {
@@ -539,6 +654,36 @@ impl Collectors {
                     .try_for_each(|p| p.process_one(&event))?;
             }
 
+            // Handle pending control requests, if any.
+            if let Some(control) = &control {
+                while let Some((request, reply)) = control.next_request() {
+                    // The client might have gone away already.
+                    let _ = reply.send(request.and_then(|request| {
+                        self.handle_control_request(&request, collect, &mut state)
+                    }));
+                }
+            }
+
+            // Periodically report lost events, if any, and update the ones
+            // served as metrics. Failing to do so should not stop the
+            // collection.
+            if lost_report.elapsed() >= LOST_EVENTS_REPORT_INTERVAL {
+                if let Err(e) = self
+                    .probes
+                    .runtime_mut()
+                    .and_then(|probes| probes.report_lost_events(&self.events_factory))
+                {
+                    warn!("Could not report lost events: {e}");
+                }
+                if let Some(server) = &metrics_server {
+                    match self.probes.runtime_mut().and_then(|p| p.lost_events()) {
+                        Ok(lost) => server.set_lost(lost),
+                        Err(e) => warn!("Could not retrieve lost events for metrics: {e}"),
+                    }
+                }
+                lost_report = Instant::now();
+            }
+
             // Then get raw events, if any.
             match self.factory.next_event(Some(Duration::from_secs(1)))? {
                 Event(mut event) => {
@@ -546,17 +691,160 @@ impl Collectors {
                         probe_stack.process_event(self.probes.runtime_mut()?, &mut event)?;
                     }
 
-                    printers
-                        .iter_mut()
-                        .try_for_each(|p| p.process_one(&event))?;
+                    state.events += 1;
+                    if let Some(server) = &metrics_server {
+                        server.process_one(&event);
+                    }
+                    let stop_now = stop.process_one(&event);
+                    match recorder.as_mut() {
+                        _ if state.paused => state.skipped += 1,
+                        Some(recorder) => recorder.process_one(event, |e| {
+                            printers.iter_mut().try_for_each(|p| p.process_one(e))
+                        })?,
+                        None => printers
+                            .iter_mut()
+                            .try_for_each(|p| p.process_one(&event))?,
+                    }
+
+                    if stop_now {
+                        info!("Stop condition reached, terminating ...");
+                        self.run.terminate();
+                    }
                 }
                 Timeout => continue,
             }
         }
 
+        // Report events lost since the last periodic report.
+        if let Err(e) = self
+            .probes
+            .runtime_mut()
+            .and_then(|probes| probes.report_lost_events(&self.events_factory))
+        {
+            warn!("Could not report lost events: {e}");
+        }
+        while let Some(event) = self.events_factory.next_event() {
+            printers
+                .iter_mut()
+                .try_for_each(|p| p.process_one(&event))?;
+        }
+
+        if let Some(recorder) = recorder {
+            debug!(
+                "Flight recorder: discarding {} buffered event(s)",
+                recorder.len()
+            );
+        }
+
         printers.iter_mut().try_for_each(|p| p.flush())?;
         self.stop()
     }
+
+    /// Handle a request received on the control socket and return the lines
+    /// of the reply.
+    fn handle_control_request(
+        &mut self,
+        request: &ControlRequest,
+        collect: &CollectArgs,
+        state: &mut ControlState,
+    ) -> Result<Vec<String>> {
+        let mut reply = Vec::new();
+
+        match request {
+            ControlRequest::Probes => {
+                let mut probes = self.probes.runtime_mut()?.attached_probes();
+                probes.sort();
+                reply = probes;
+            }
+            ControlRequest::AddProbe(target) => {
+                let known_kernel_types = &self.known_kernel_types;
+                let probes = probe_from_cli(target, |symbol: &Symbol| {
+                    known_kernel_types.iter().any(|t| {
+                        symbol
+                            .parameter_offset(t)
+                            .is_ok_and(|offset| offset.is_some())
+                    })
+                })?;
+                if probes.is_empty() {
+                    bail!("No probe compatible with the loaded collectors matches {target}");
+                }
+
+                let runtime = self.probes.runtime_mut()?;
+                for mut probe in probes {
+                    if collect.stack {
+                        probe.set_option(ProbeOption::StackTrace)?;
+                    }
+
+                    if let Err(e) = runtime.attach_generic_probe(&mut probe) {
+                        reply.push(format!("could not add {probe}: {e}"));
+                        continue;
+                    }
+
+                    info!("Probe added to {probe}");
+                    reply.push(format!("added {probe}"));
+                }
+            }
+            ControlRequest::RemoveProbe(target) => {
+                let runtime = self.probes.runtime_mut()?;
+                let attached = runtime.attached_probes();
+
+                let probes: Vec<_> = probe_from_cli(target, |_| true)?
+                    .into_iter()
+                    .filter(|p| attached.contains(&p.key()))
+                    .collect();
+                if probes.is_empty() {
+                    bail!("No attached probe matches {target}");
+                }
+
+                // Errors are reported per probe, so the reply reflects the
+                // probes which were actually removed.
+                for probe in probes.iter() {
+                    if let Err(e) = runtime.detach_generic_probe(probe) {
+                        reply.push(format!("could not remove {probe}: {e}"));
+                        continue;
+                    }
+
+                    info!("Probe removed from {probe}");
+                    reply.push(format!("removed {probe}"));
+                }
+            }
+            ControlRequest::Pause => {
+                info!("Pausing the output of events");
+                state.paused = true;
+            }
+            ControlRequest::Resume => {
+                info!("Resuming the output of events");
+                state.paused = false;
+            }
+            ControlRequest::Counters => {
+                reply.push(format!("events: {}", state.events));
+                reply.push(format!("paused: {}", state.paused));
+                reply.push(format!("skipped while paused: {}", state.skipped));
+
+                let mut lost: Vec<_> = self
+                    .probes
+                    .runtime_mut()?
+                    .lost_events()?
+                    .into_iter()
+                    .collect();
+                lost.sort();
+                lost.iter()
+                    .for_each(|(probe, count)| reply.push(format!("lost from {probe}: {count}")));
+            }
+            ControlRequest::Marker(message) => {
+                self.events_factory.add_event(|event| {
+                    event.insert_section(
+                        SectionId::Marker,
+                        Box::new(MarkerEvent {
+                            message: message.clone(),
+                        }),
+                    )
+                })?;
+            }
+        }
+
+        Ok(reply)
+    }
 }
 
 pub(crate) struct CollectRunner {}
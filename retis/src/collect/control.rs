@@ -0,0 +1,350 @@
+//! # Control
+//!
+//! Local control socket of a running collection, and the `ctl` command used to
+//! drive it. Requests and replies are line based: the client sends a single
+//! request line, the collector replies with a status line ("ok" or
+//! "error: MESSAGE") followed by the reply data, if any, and closes the
+//! connection.
+
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use clap::{Parser, Subcommand};
+use log::warn;
+
+use crate::{
+    cli::*,
+    helpers::{
+        signals::Running,
+        socket::{SocketAddr, SocketStream},
+    },
+    module::Modules,
+};
+
+/// Default path of the control socket.
+pub(crate) const DEFAULT_CONTROL_SOCKET: &str = "retis.sock";
+
+/// Requests supported by the control socket.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ControlRequest {
+    /// List the attached probes.
+    Probes,
+    /// Attach probes, following the --probe format.
+    AddProbe(String),
+    /// Detach probes, following the --probe format.
+    RemoveProbe(String),
+    /// Stop writing events to the outputs.
+    Pause,
+    /// Resume writing events to the outputs.
+    Resume,
+    /// Dump the collection counters.
+    Counters,
+    /// Inject a marker event.
+    Marker(String),
+}
+
+impl FromStr for ControlRequest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (cmd, arg) = match s.split_once(' ') {
+            Some((cmd, arg)) => (cmd, arg.trim()),
+            None => (s, ""),
+        };
+
+        use ControlRequest::*;
+        Ok(match (cmd, arg) {
+            ("probes", "") => Probes,
+            ("add-probe", probe) if !probe.is_empty() => AddProbe(probe.to_string()),
+            ("remove-probe", probe) if !probe.is_empty() => RemoveProbe(probe.to_string()),
+            ("pause", "") => Pause,
+            ("resume", "") => Resume,
+            ("counters", "") => Counters,
+            ("marker", message) => Marker(message.to_string()),
+            _ => bail!("Invalid request '{s}'"),
+        })
+    }
+}
+
+impl fmt::Display for ControlRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ControlRequest::*;
+        match self {
+            Probes => write!(f, "probes"),
+            AddProbe(probe) => write!(f, "add-probe {probe}"),
+            RemoveProbe(probe) => write!(f, "remove-probe {probe}"),
+            Pause => write!(f, "pause"),
+            Resume => write!(f, "resume"),
+            Counters => write!(f, "counters"),
+            Marker(message) => write!(f, "marker {message}"),
+        }
+    }
+}
+
+/// State of a collection exposed and controlled through the control socket.
+#[derive(Default)]
+pub(crate) struct ControlState {
+    /// Is writing events to the outputs paused?
+    pub(crate) paused: bool,
+    /// Number of events retrieved.
+    pub(crate) events: u64,
+    /// Number of events not written to the outputs because those were paused.
+    pub(crate) skipped: u64,
+}
+
+/// Reply to a control request, sent back to the control socket thread.
+type ControlReply = Result<Vec<String>>;
+
+/// Control socket of a running collection. Connections are handled in a
+/// dedicated thread so clients can't stall the collection, requests being
+/// passed to the collection loop, see `next_request`.
+pub(crate) struct ControlSocket {
+    requests: Option<mpsc::Receiver<(Result<ControlRequest>, mpsc::Sender<ControlReply>)>>,
+    run: Running,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl ControlSocket {
+    pub(crate) fn new(path: PathBuf) -> Result<Self> {
+        let listener = SocketAddr::Unix(path).listen()?;
+        let (tx, rx) = mpsc::channel();
+        let run = Running::new();
+
+        let thread_run = run.clone();
+        let thread = thread::Builder::new()
+            .name("control".into())
+            .spawn(move || {
+                while thread_run.running() {
+                    let stream = match listener.accept(&thread_run) {
+                        Ok(Some(stream)) => stream,
+                        Ok(None) => break,
+                        Err(e) => {
+                            warn!("Could not accept control connection: {e}");
+                            thread::sleep(Duration::from_millis(100));
+                            continue;
+                        }
+                    };
+
+                    if let Err(e) = Self::handle(stream, &tx) {
+                        warn!("Could not handle control request: {e}");
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            requests: Some(rx),
+            run,
+            thread: Some(thread),
+        })
+    }
+
+    /// Read a request from a client, pass it to the collection loop and write
+    /// back its reply.
+    fn handle(
+        mut stream: SocketStream,
+        requests: &mpsc::Sender<(Result<ControlRequest>, mpsc::Sender<ControlReply>)>,
+    ) -> Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+
+        let mut line = String::new();
+        let request = match BufReader::new(stream.try_clone()?).read_line(&mut line) {
+            Ok(_) => ControlRequest::from_str(&line),
+            Err(e) => Err(anyhow!("Could not read request: {e}")),
+        };
+
+        // Both channels are closed when the collection stops.
+        let (tx, rx) = mpsc::channel();
+        requests
+            .send((request, tx))
+            .map_err(|_| anyhow!("Collection stopped"))?;
+        let reply = rx.recv().map_err(|_| anyhow!("Collection stopped"))?;
+
+        match reply {
+            Ok(lines) => {
+                let mut out = String::from("ok\n");
+                lines.iter().for_each(|l| {
+                    out.push_str(l);
+                    out.push('\n');
+                });
+                stream.write_all(out.as_bytes())?;
+            }
+            Err(e) => stream.write_all(format!("error: {e}\n").as_bytes())?,
+        }
+        Ok(())
+    }
+
+    /// Retrieve a pending request, if any, without waiting. The reply must be
+    /// sent using the returned channel.
+    pub(crate) fn next_request(
+        &self,
+    ) -> Option<(Result<ControlRequest>, mpsc::Sender<ControlReply>)> {
+        self.requests.as_ref()?.try_recv().ok()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        // Closing the requests channel unblocks the thread if it's waiting for
+        // a reply. Joining it removes the socket from the filesystem.
+        self.requests = None;
+        self.run.terminate();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Control a running collection.
+///
+/// Sends a request to a collection started with `collect --control-socket` and prints
+/// its reply.
+#[derive(Parser, Debug, Default)]
+#[command(name = "ctl")]
+pub(crate) struct Ctl {
+    /// Path of the control socket of the collection.
+    #[arg(short, long, default_value = DEFAULT_CONTROL_SOCKET)]
+    pub(super) socket: PathBuf,
+
+    #[command(subcommand)]
+    pub(super) command: CtlCommand,
+}
+
+#[derive(Subcommand, Debug, Default)]
+pub(crate) enum CtlCommand {
+    /// List the attached probes.
+    #[default]
+    Probes,
+    /// Attach probes. Probes follow the [TYPE:]TARGET pattern, see `collect --probe`.
+    AddProbe { probe: String },
+    /// Detach probes. Probes follow the [TYPE:]TARGET pattern, see `collect --probe`.
+    RemoveProbe { probe: String },
+    /// Stop writing events to the outputs; events are discarded until resumed.
+    Pause,
+    /// Resume writing events to the outputs.
+    Resume,
+    /// Dump the collection counters.
+    Counters,
+    /// Inject a marker event, with an optional message.
+    Marker { message: Vec<String> },
+}
+
+impl From<&CtlCommand> for ControlRequest {
+    fn from(cmd: &CtlCommand) -> Self {
+        match cmd {
+            CtlCommand::Probes => ControlRequest::Probes,
+            CtlCommand::AddProbe { probe } => ControlRequest::AddProbe(probe.clone()),
+            CtlCommand::RemoveProbe { probe } => ControlRequest::RemoveProbe(probe.clone()),
+            CtlCommand::Pause => ControlRequest::Pause,
+            CtlCommand::Resume => ControlRequest::Resume,
+            CtlCommand::Counters => ControlRequest::Counters,
+            CtlCommand::Marker { message } => ControlRequest::Marker(message.join(" ")),
+        }
+    }
+}
+
+impl SubCommandParserRunner for Ctl {
+    fn run(&mut self, _: Modules) -> Result<()> {
+        let mut stream = SocketAddr::Unix(self.socket.clone()).connect()?;
+        stream.write_all(format!("{}\n", ControlRequest::from(&self.command)).as_bytes())?;
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply)?;
+
+        let mut lines = reply.lines();
+        match lines.next() {
+            Some("ok") => lines.for_each(|l| println!("{l}")),
+            Some(status) => match status.strip_prefix("error: ") {
+                Some(e) => bail!("{e}"),
+                None => bail!("Invalid reply '{status}'"),
+            },
+            None => bail!("No reply received"),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_from_str() {
+        use ControlRequest::*;
+
+        for (s, req) in [
+            ("probes", Probes),
+            ("add-probe tp:skb:*\n", AddProbe("tp:skb:*".to_string())),
+            (
+                "remove-probe  kprobe:tcp_v4_rcv",
+                RemoveProbe("kprobe:tcp_v4_rcv".to_string()),
+            ),
+            ("pause", Pause),
+            ("resume", Resume),
+            ("counters", Counters),
+            ("marker", Marker("".to_string())),
+            ("marker test 1", Marker("test 1".to_string())),
+        ] {
+            assert_eq!(ControlRequest::from_str(s).unwrap(), req);
+            assert_eq!(ControlRequest::from_str(&req.to_string()).unwrap(), req);
+        }
+
+        assert!(ControlRequest::from_str("").is_err());
+        assert!(ControlRequest::from_str("add-probe").is_err());
+        assert!(ControlRequest::from_str("pause now").is_err());
+        assert!(ControlRequest::from_str("foo").is_err());
+    }
+
+    #[test]
+    fn control_socket() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("retis-ctl-{}.sock", std::process::id()));
+        let control = ControlSocket::new(path.clone())?;
+        assert!(control.next_request().is_none());
+
+        // An idle client does not block the collection loop.
+        let _idle = SocketAddr::Unix(path.clone()).connect()?;
+        assert!(control.next_request().is_none());
+
+        let client = thread::spawn({
+            let path = path.clone();
+            move || -> Result<String> {
+                let mut stream = SocketAddr::Unix(path).connect()?;
+                stream.write_all(b"counters\n")?;
+                let mut reply = String::new();
+                stream.read_to_string(&mut reply)?;
+                Ok(reply)
+            }
+        });
+
+        // The idle client is served first, its request failing once its read
+        // times out.
+        let reply = loop {
+            match control.next_request() {
+                Some((Ok(request), reply)) => {
+                    assert_eq!(request, ControlRequest::Counters);
+                    break reply;
+                }
+                Some((Err(_), reply)) => {
+                    let _ = reply.send(Err(anyhow!("invalid request")));
+                }
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        reply.send(Ok(vec!["events: 42".to_string()])).unwrap();
+        assert_eq!(client.join().unwrap()?, "ok\nevents: 42\n");
+
+        drop(control);
+        assert!(!path.exists());
+        Ok(())
+    }
+}
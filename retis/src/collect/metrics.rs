@@ -0,0 +1,366 @@
+//! # Metrics
+//!
+//! Aggregates counters from the events being collected and serves them over
+//! HTTP in the Prometheus text format, so a collection can be monitored
+//! without processing its events.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+
+use crate::{
+    events::*,
+    helpers::{
+        signals::Running,
+        socket::{SocketAddr, SocketStream},
+    },
+};
+
+/// Counters aggregated from the collected events.
+#[derive(Default)]
+pub(crate) struct EventMetrics {
+    /// Events per probe.
+    events: BTreeMap<String, u64>,
+    /// Drops per (reason, subsystem).
+    drops: BTreeMap<(String, String), u64>,
+    /// Nftables verdicts per (table, chain, verdict).
+    verdicts: BTreeMap<(String, String, String), u64>,
+    /// Conntrack states.
+    ct_states: BTreeMap<String, u64>,
+    /// Events lost per probe, see `set_lost`.
+    lost: BTreeMap<String, u64>,
+}
+
+impl EventMetrics {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for a collected event.
+    pub(crate) fn process_one(&mut self, event: &Event) {
+        let probe = event
+            .get_section::<KernelEvent>(SectionId::Kernel)
+            .map(|k| &k.symbol)
+            .or_else(|| {
+                event
+                    .get_section::<UserEvent>(SectionId::Userspace)
+                    .map(|u| &u.symbol)
+            });
+        if let Some(probe) = probe {
+            *self.events.entry(probe.clone()).or_default() += 1;
+        }
+
+        if let Some(drop) = event.get_section::<SkbDropEvent>(SectionId::SkbDrop) {
+            *self
+                .drops
+                .entry((
+                    drop.drop_reason.clone(),
+                    drop.subsys.clone().unwrap_or_default(),
+                ))
+                .or_default() += 1;
+        }
+
+        if let Some(nft) = event.get_section::<NftEvent>(SectionId::Nft) {
+            *self
+                .verdicts
+                .entry((
+                    nft.table_name.clone(),
+                    nft.chain_name.clone(),
+                    nft.verdict.clone(),
+                ))
+                .or_default() += 1;
+        }
+
+        if let Some(ct) = event.get_section::<CtEvent>(SectionId::Ct) {
+            *self
+                .ct_states
+                .entry(ct.state.name().to_string())
+                .or_default() += 1;
+        }
+    }
+
+    /// Update the number of events lost per probe since the collection
+    /// started.
+    pub(crate) fn set_lost(&mut self, lost: HashMap<String, u64>) {
+        self.lost = lost.into_iter().collect();
+    }
+
+    /// Render the metrics in the Prometheus text format.
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+
+        let mut metric = |name: &str, help: &str, samples: Vec<(Vec<(&str, &str)>, u64)>| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            for (labels, value) in samples.iter() {
+                let labels: Vec<String> = labels
+                    .iter()
+                    .map(|(k, v)| format!("{k}=\"{}\"", escape(v)))
+                    .collect();
+                let _ = writeln!(out, "{name}{{{}}} {value}", labels.join(","));
+            }
+        };
+
+        metric(
+            "retis_events_total",
+            "Number of events collected per probe.",
+            self.events
+                .iter()
+                .map(|(probe, n)| (vec![("probe", probe.as_str())], *n))
+                .collect(),
+        );
+        metric(
+            "retis_skb_drops_total",
+            "Number of packet drops per reason and subsystem.",
+            self.drops
+                .iter()
+                .map(|((reason, subsys), n)| {
+                    (
+                        vec![("reason", reason.as_str()), ("subsys", subsys.as_str())],
+                        *n,
+                    )
+                })
+                .collect(),
+        );
+        metric(
+            "retis_nft_verdicts_total",
+            "Number of nftables verdicts per table and chain.",
+            self.verdicts
+                .iter()
+                .map(|((table, chain, verdict), n)| {
+                    (
+                        vec![
+                            ("table", table.as_str()),
+                            ("chain", chain.as_str()),
+                            ("verdict", verdict.as_str()),
+                        ],
+                        *n,
+                    )
+                })
+                .collect(),
+        );
+        metric(
+            "retis_ct_states_total",
+            "Number of packets per conntrack state.",
+            self.ct_states
+                .iter()
+                .map(|(state, n)| (vec![("state", state.as_str())], *n))
+                .collect(),
+        );
+
+        metric(
+            "retis_lost_events_total",
+            "Number of events lost per probe.",
+            self.lost
+                .iter()
+                .map(|(probe, n)| (vec![("probe", probe.as_str())], *n))
+                .collect(),
+        );
+
+        out
+    }
+}
+
+/// Escape a Prometheus label value.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// HTTP endpoint serving the metrics. Requests are served from a dedicated
+/// thread so clients can't stall the collection, the metrics being shared with
+/// the collection loop.
+pub(crate) struct MetricsServer {
+    metrics: Arc<Mutex<EventMetrics>>,
+    run: Running,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub(crate) fn new(addr: &net::SocketAddr) -> Result<Self> {
+        let listener = SocketAddr::Tcp(addr.to_string()).listen()?;
+        let metrics = Arc::new(Mutex::new(EventMetrics::new()));
+        let run = Running::new();
+
+        let (thread_metrics, thread_run) = (metrics.clone(), run.clone());
+        let thread = thread::Builder::new()
+            .name("metrics".into())
+            .spawn(move || {
+                while thread_run.running() {
+                    let stream = match listener.accept(&thread_run) {
+                        Ok(Some(stream)) => stream,
+                        Ok(None) => break,
+                        Err(e) => {
+                            warn!("Could not accept metrics connection: {e}");
+                            thread::sleep(Duration::from_millis(100));
+                            continue;
+                        }
+                    };
+
+                    if let Err(e) = Self::handle(stream, &thread_metrics) {
+                        warn!("Could not reply to metrics request: {e}");
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            metrics,
+            run,
+            thread: Some(thread),
+        })
+    }
+
+    /// Account for a collected event.
+    pub(crate) fn process_one(&self, event: &Event) {
+        if let Ok(mut metrics) = self.metrics.lock() {
+            metrics.process_one(event);
+        }
+    }
+
+    /// Update the number of events lost per probe, see
+    /// `EventMetrics::set_lost`.
+    pub(crate) fn set_lost(&self, lost: HashMap<String, u64>) {
+        if let Ok(mut metrics) = self.metrics.lock() {
+            metrics.set_lost(lost);
+        }
+    }
+
+    /// Read a request from a client and reply to it. Only the metrics are
+    /// served, other paths being answered with an error.
+    fn handle(mut stream: SocketStream, metrics: &Mutex<EventMetrics>) -> Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+
+        // Only the request line matters, but the headers are consumed so the
+        // connection can be closed cleanly.
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request = String::new();
+        if reader.read_line(&mut request).is_err() {
+            request.clear();
+        }
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap_or(0) > 2 {
+            line.clear();
+        }
+
+        let (status, body) = match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+            ["GET", "/metrics"] | ["GET", "/"] => (
+                "200 OK",
+                metrics
+                    .lock()
+                    .map_err(|_| anyhow!("Metrics are not available"))?
+                    .render(),
+            ),
+            _ => ("404 Not Found", String::from("Not found\n")),
+        };
+
+        stream.write_all(
+            format!(
+                "HTTP/1.0 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .as_bytes(),
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.run.terminate();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics() {
+        let mut metrics = EventMetrics::new();
+
+        let mut event = Event::new();
+        event
+            .insert_section(
+                SectionId::Kernel,
+                Box::new(KernelEvent {
+                    symbol: "skb:kfree_skb".to_string(),
+                    probe_type: "raw_tracepoint".to_string(),
+                    stack_trace: None,
+                }),
+            )
+            .unwrap();
+        event
+            .insert_section(
+                SectionId::SkbDrop,
+                Box::new(SkbDropEvent {
+                    subsys: None,
+                    drop_reason: "NO_SOCKET".to_string(),
+                }),
+            )
+            .unwrap();
+        metrics.process_one(&event);
+        metrics.process_one(&event);
+
+        metrics.set_lost(HashMap::from([("ip_rcv".to_string(), 3)]));
+        let out = metrics.render();
+        assert!(out.contains("# TYPE retis_events_total counter\n"));
+        assert!(out.contains("retis_events_total{probe=\"skb:kfree_skb\"} 2\n"));
+        assert!(out.contains("retis_skb_drops_total{reason=\"NO_SOCKET\",subsys=\"\"} 2\n"));
+        assert!(out.contains("retis_lost_events_total{probe=\"ip_rcv\"} 3\n"));
+        assert!(!out.contains("retis_nft_verdicts_total{"));
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+
+    #[test]
+    fn metrics_server() -> Result<()> {
+        use std::{io::Read, net::TcpListener};
+
+        let addr = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        let server = MetricsServer::new(&addr)?;
+
+        let mut event = Event::new();
+        event.insert_section(
+            SectionId::Kernel,
+            Box::new(KernelEvent {
+                symbol: "ip_rcv".to_string(),
+                probe_type: "kprobe".to_string(),
+                stack_trace: None,
+            }),
+        )?;
+        server.process_one(&event);
+
+        // A client not sending its request only delays the others, up to the
+        // read timeout.
+        let idle = SocketAddr::Tcp(addr.to_string()).connect()?;
+
+        let get = |path: &str| -> Result<String> {
+            let mut stream = SocketAddr::Tcp(addr.to_string()).connect()?;
+            stream.write_all(format!("GET {path} HTTP/1.0\r\n\r\n").as_bytes())?;
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply)?;
+            Ok(reply)
+        };
+        let reply = get("/metrics")?;
+        assert!(reply.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(reply.contains("retis_events_total{probe=\"ip_rcv\"} 1\n"));
+        assert!(get("/foo")?.starts_with("HTTP/1.0 404 Not Found\r\n"));
+
+        drop(idle);
+        drop(server);
+        Ok(())
+    }
+}
@@ -21,3 +21,8 @@ pub(crate) mod collector;
 pub(crate) use collector::*;
 
 pub(crate) mod cli;
+pub(crate) mod control;
+pub(crate) mod metrics;
+pub(crate) mod output;
+pub(crate) mod recorder;
+pub(crate) mod stop;
@@ -0,0 +1,371 @@
+//! # Output
+//!
+//! Handles writing the collected events to files, optionally compressing them
+//! and rotating them based on their size and/or age, and streaming them to
+//! sockets.
+
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use log::{debug, warn};
+
+use crate::{
+    events::{
+        file::{FileCompression, FileFormat},
+        *,
+    },
+    helpers::socket::SocketAddr,
+    process::display::*,
+};
+
+/// Conditions triggering the rotation of the output file.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RotationConfig {
+    /// Maximum size of a file, in bytes.
+    pub(crate) max_size: Option<u64>,
+    /// Maximum duration covered by a single file.
+    pub(crate) max_duration: Option<Duration>,
+    /// Maximum number of files to keep, including the current one. Older files
+    /// are removed.
+    pub(crate) max_files: Option<usize>,
+}
+
+/// Writer keeping track of the number of bytes written to the inner writer. It
+/// sits on top of any buffering or compression so the count is up to date.
+struct CountingWriter<W: Write> {
+    inner: W,
+    written: Rc<Cell<u64>>,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.written.set(self.written.get() + len as u64);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes events to a file in the JSON or binary format. When rotation is configured,
+/// new files are created once the current one reaches its limits: the first
+/// file uses the given path, the following ones have an increasing index
+/// appended to it (e.g. `retis.data`, `retis.data.1`, `retis.data.2`).
+///
+/// Each file starts with the startup event of the collection so it can be
+/// processed on its own.
+pub(crate) struct EventsFile {
+    /// Base path of the output files.
+    path: PathBuf,
+    rotation: RotationConfig,
+    format: FileFormat,
+    compression: FileCompression,
+    printer: PrintEvent,
+    /// Number of bytes written to the current file.
+    written: Rc<Cell<u64>>,
+    /// When the current file was opened.
+    opened: Instant,
+    /// Index of the current file.
+    index: usize,
+    /// Files still on disk, oldest first.
+    files: VecDeque<PathBuf>,
+    /// Sections of the startup event, replayed at the beginning of each file.
+    startup: Option<(CommonEvent, StartupEvent)>,
+}
+
+impl EventsFile {
+    pub(crate) fn new(
+        path: &Path,
+        rotation: RotationConfig,
+        format: FileFormat,
+        compression: FileCompression,
+    ) -> Result<Self> {
+        let written = Rc::new(Cell::new(0));
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            printer: Self::open(path, format, compression, &written)?,
+            rotation,
+            format,
+            compression,
+            written,
+            opened: Instant::now(),
+            index: 0,
+            files: VecDeque::from([path.to_path_buf()]),
+            startup: None,
+        })
+    }
+
+    /// Open a new output file and return the associated printer.
+    fn open(
+        path: &Path,
+        format: FileFormat,
+        compression: FileCompression,
+        written: &Rc<Cell<u64>>,
+    ) -> Result<PrintEvent> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .or_else(|_| bail!("Could not create or open '{}'", path.display()))?;
+
+        written.set(0);
+        Ok(PrintEvent::new(
+            Box::new(CountingWriter {
+                inner: BufWriter::new(compression.writer(file)?),
+                written: Rc::clone(written),
+            }),
+            format.into(),
+        ))
+    }
+
+    /// Path of the output file for a given index.
+    fn indexed_path(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.path.clone(),
+            _ => {
+                let mut path = self.path.clone().into_os_string();
+                path.push(format!(".{index}"));
+                PathBuf::from(path)
+            }
+        }
+    }
+
+    /// Should the current file be rotated before writing a new event?
+    fn should_rotate(&self) -> bool {
+        self.rotation
+            .max_size
+            .is_some_and(|max| self.written.get() >= max)
+            || self
+                .rotation
+                .max_duration
+                .is_some_and(|max| self.opened.elapsed() >= max)
+    }
+
+    /// Close the current file and start writing to a new one.
+    fn rotate(&mut self) -> Result<()> {
+        self.printer.flush()?;
+
+        self.index += 1;
+        let path = self.indexed_path(self.index);
+        debug!("Rotating output file to {}", path.display());
+
+        // Replacing the printer drops the previous one, which finalizes its
+        // compressed stream (if any).
+        self.printer = Self::open(&path, self.format, self.compression, &self.written)?;
+        self.opened = Instant::now();
+        self.files.push_back(path);
+
+        if let Some(max) = self.rotation.max_files {
+            while self.files.len() > max {
+                if let Some(old) = self.files.pop_front() {
+                    if let Err(e) = fs::remove_file(&old) {
+                        warn!("Could not remove old output file {}: {e}", old.display());
+                    }
+                }
+            }
+        }
+
+        // Make the new file self-contained.
+        if let Some((common, startup)) = &self.startup {
+            let mut event = Event::new();
+            event.insert_section(SectionId::Common, Box::new(common.clone()))?;
+            event.insert_section(SectionId::Startup, Box::new(startup.clone()))?;
+            self.printer.process_one(&event)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl EventOutput for EventsFile {
+    fn process_one(&mut self, e: &Event) -> Result<()> {
+        match e.get_section::<StartupEvent>(SectionId::Startup) {
+            Some(startup) => {
+                if self.startup.is_none() {
+                    let common = e
+                        .get_section::<CommonEvent>(SectionId::Common)
+                        .cloned()
+                        .unwrap_or_default();
+                    self.startup = Some((common, startup.clone()));
+                }
+            }
+            None => {
+                if self.should_rotate() {
+                    self.rotate()?;
+                }
+            }
+        }
+
+        self.printer.process_one(e)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.printer.flush()
+    }
+}
+
+/// Streams events to a socket in the JSON format, one event per line. If the
+/// peer goes away streaming is stopped, but the collection goes on.
+pub(crate) struct EventsSocket {
+    addr: SocketAddr,
+    printer: Option<PrintEvent>,
+}
+
+impl EventsSocket {
+    pub(crate) fn new(addr: &SocketAddr) -> Result<Self> {
+        // Events are not buffered so they are sent to the peer right away.
+        let printer = PrintEvent::new(Box::new(addr.connect()?), PrintEventFormat::Json);
+
+        Ok(Self {
+            addr: addr.clone(),
+            printer: Some(printer),
+        })
+    }
+
+    fn handle_error(&mut self, res: Result<()>) {
+        if let Err(e) = res {
+            warn!("Stopped streaming events to {}: {e}", self.addr);
+            self.printer = None;
+        }
+    }
+}
+
+impl EventOutput for EventsSocket {
+    fn process_one(&mut self, e: &Event) -> Result<()> {
+        if let Some(printer) = self.printer.as_mut() {
+            let res = printer.process_one(e);
+            self.handle_error(res);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if let Some(printer) = self.printer.as_mut() {
+            let res = printer.flush();
+            self.handle_error(res);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::events::file::FileEventsFactory;
+
+    fn startup_event() -> Event {
+        let mut event = Event::new();
+        event
+            .insert_section(SectionId::Common, Box::<CommonEvent>::default())
+            .unwrap();
+        event
+            .insert_section(
+                SectionId::Startup,
+                Box::new(StartupEvent {
+                    retis_version: "test".to_string(),
+                    clock_monotonic_offset: TimeSpec::new(0, 0),
+                    sampling: None,
+                }),
+            )
+            .unwrap();
+        event
+    }
+
+    fn common_event(timestamp: u64) -> Event {
+        let mut event = Event::new();
+        event
+            .insert_section(
+                SectionId::Common,
+                Box::new(CommonEvent {
+                    timestamp,
+                    ..Default::default()
+                }),
+            )
+            .unwrap();
+        event
+    }
+
+    #[test]
+    fn rotate_by_size() -> Result<()> {
+        let dir = env::temp_dir().join(format!("retis-rotate-{}", process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("retis.data");
+
+        let mut out = EventsFile::new(
+            &path,
+            RotationConfig {
+                max_size: Some(1),
+                max_files: Some(2),
+                ..Default::default()
+            },
+            FileFormat::Json,
+            FileCompression::None,
+        )?;
+
+        // Rotation must not depend on the output being flushed.
+        out.process_one(&startup_event())?;
+        for ts in 0..3 {
+            out.process_one(&common_event(ts))?;
+        }
+        out.flush()?;
+
+        // Only the last two files are kept.
+        assert!(!path.exists());
+        assert!(!dir.join("retis.data.1").exists());
+        for file in ["retis.data.2", "retis.data.3"] {
+            let mut factory = FileEventsFactory::new(dir.join(file))?;
+            let first = factory.next_event()?.unwrap();
+            assert!(first
+                .get_section::<StartupEvent>(SectionId::Startup)
+                .is_some());
+            assert!(factory.next_event()?.is_some());
+            assert!(factory.next_event()?.is_none());
+        }
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_binary_output() -> Result<()> {
+        let dir = env::temp_dir().join(format!("retis-compress-{}", process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("retis.bin.zst");
+
+        {
+            let mut out = EventsFile::new(
+                &path,
+                RotationConfig::default(),
+                FileFormat::Binary,
+                FileCompression::Zstd,
+            )?;
+            out.process_one(&startup_event())?;
+            out.process_one(&common_event(42))?;
+            out.flush()?;
+        }
+
+        let mut factory = FileEventsFactory::new(&path)?;
+        assert_eq!(factory.format(), FileFormat::Binary);
+        assert_eq!(factory.compression(), FileCompression::Zstd);
+        assert!(factory.next_event()?.is_some());
+        assert!(factory.next_event()?.is_some());
+        assert!(factory.next_event()?.is_none());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
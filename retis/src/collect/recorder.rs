@@ -0,0 +1,261 @@
+//! # Flight recorder
+//!
+//! Keeps the latest collected events in a bounded in-memory ring and only
+//! outputs them, followed by the events of a post-trigger window, once an
+//! event matching a trigger is seen.
+
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use log::{debug, info};
+
+use crate::{
+    events::*,
+    helpers::{size::parse_size, time::parse_duration},
+    process::filter::EventFilter,
+};
+
+/// Bound of the flight recorder ring.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RecorderLimit {
+    /// Maximum size of the buffered events, in bytes.
+    Size(u64),
+    /// Maximum time span covered by the buffered events.
+    Duration(Duration),
+}
+
+impl FromStr for RecorderLimit {
+    type Err = anyhow::Error;
+
+    /// Parses a duration (e.g. "2s", "500ms") or a size (e.g. "100M"). Values
+    /// without unit are durations in seconds.
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(duration) = parse_duration(s) {
+            return Ok(Self::Duration(duration));
+        }
+        match parse_size(s) {
+            Ok(size) => Ok(Self::Size(size)),
+            Err(_) => bail!(
+                "Invalid flight recorder limit '{s}', expected a duration (e.g. 2s) or a size (e.g. 100M)"
+            ),
+        }
+    }
+}
+
+/// Flight recorder. Events are buffered as-is and only serialized when they
+/// are output; their binary size is only computed when the buffer is bounded by
+/// a size.
+pub(crate) struct FlightRecorder {
+    limit: RecorderLimit,
+    trigger: EventFilter,
+    /// Duration during which events are output after the trigger matched.
+    post: Duration,
+    /// Buffered events: (timestamp, binary size, event).
+    ring: VecDeque<(u64, u64, Event)>,
+    /// Size of the buffered events, in bytes.
+    size: u64,
+    /// End of the current post-trigger window, if any.
+    until: Option<Instant>,
+    /// Scratch buffer used to compute the binary size of events.
+    buf: Vec<u8>,
+}
+
+impl FlightRecorder {
+    pub(crate) fn new(limit: RecorderLimit, trigger: EventFilter, post: Duration) -> Self {
+        Self {
+            limit,
+            trigger,
+            post,
+            ring: VecDeque::new(),
+            size: 0,
+            until: None,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Process a collected event. Events which should be output (buffered ones
+    /// once the trigger matched, and the ones in the post-trigger window) are
+    /// given to `output`.
+    pub(crate) fn process_one<F>(&mut self, event: Event, mut output: F) -> Result<()>
+    where
+        F: FnMut(&Event) -> Result<()>,
+    {
+        let matched = self.trigger.matches(&event);
+
+        // Within a post-trigger window, events are output directly. Matching
+        // events extend the window.
+        if let Some(until) = self.until {
+            if Instant::now() < until {
+                if matched {
+                    self.until = Some(Instant::now() + self.post);
+                }
+                return output(&event);
+            }
+
+            debug!("Flight recorder post-trigger window ended");
+            self.until = None;
+        }
+
+        if matched {
+            info!(
+                "Flight recorder triggered, writing {} buffered event(s)",
+                self.ring.len()
+            );
+
+            while let Some((_, _, buffered)) = self.ring.pop_front() {
+                output(&buffered)?;
+            }
+            self.size = 0;
+
+            self.until = Some(Instant::now() + self.post);
+            return output(&event);
+        }
+
+        self.push(event)
+    }
+
+    /// Store an event in the ring, evicting the oldest ones if needed.
+    fn push(&mut self, event: Event) -> Result<()> {
+        let timestamp = event
+            .get_section::<CommonEvent>(SectionId::Common)
+            .map(|c| c.timestamp)
+            .unwrap_or_default();
+
+        let size = match self.limit {
+            RecorderLimit::Size(_) => {
+                self.buf.clear();
+                event.to_binary(&mut self.buf)?;
+                self.buf.len() as u64
+            }
+            RecorderLimit::Duration(_) => 0,
+        };
+        self.size += size;
+        self.ring.push_back((timestamp, size, event));
+
+        while let Some((oldest, size, _)) = self.ring.front() {
+            let evict = match self.limit {
+                RecorderLimit::Size(max) => self.size > max,
+                RecorderLimit::Duration(max) => {
+                    timestamp.saturating_sub(*oldest) > max.as_nanos() as u64
+                }
+            };
+            if !evict {
+                break;
+            }
+
+            self.size -= *size;
+            self.ring.pop_front();
+        }
+
+        Ok(())
+    }
+
+    /// Number of events currently buffered.
+    pub(crate) fn len(&self) -> usize {
+        self.ring.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp: u64, reason: Option<&str>) -> Event {
+        let mut event = Event::new();
+        event
+            .insert_section(
+                SectionId::Common,
+                Box::new(CommonEvent {
+                    timestamp,
+                    ..Default::default()
+                }),
+            )
+            .unwrap();
+        if let Some(reason) = reason {
+            event
+                .insert_section(
+                    SectionId::SkbDrop,
+                    Box::new(SkbDropEvent {
+                        subsys: None,
+                        drop_reason: reason.to_string(),
+                    }),
+                )
+                .unwrap();
+        }
+        event
+    }
+
+    #[test]
+    fn limit_from_str() -> Result<()> {
+        assert_eq!(
+            RecorderLimit::from_str("2s")?,
+            RecorderLimit::Duration(Duration::from_secs(2))
+        );
+        assert_eq!(
+            RecorderLimit::from_str("10")?,
+            RecorderLimit::Duration(Duration::from_secs(10))
+        );
+        assert_eq!(
+            RecorderLimit::from_str("1M")?,
+            RecorderLimit::Size(1024 * 1024)
+        );
+        assert!(RecorderLimit::from_str("1X").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn flight_recorder() -> Result<()> {
+        let mut recorder = FlightRecorder::new(
+            RecorderLimit::Duration(Duration::from_nanos(10)),
+            EventFilter::from_str("skb-drop.drop_reason == NETFILTER_DROP")?,
+            Duration::from_secs(3600),
+        );
+
+        let mut out = Vec::new();
+        for ts in 0..20 {
+            recorder.process_one(event(ts, Some("NO_SOCKET")), |e| {
+                out.push(
+                    e.get_section::<CommonEvent>(SectionId::Common)
+                        .unwrap()
+                        .timestamp,
+                );
+                Ok(())
+            })?;
+        }
+        assert!(out.is_empty());
+        assert_eq!(recorder.len(), 11);
+
+        // Trigger: buffered events are output, then following events are
+        // output directly.
+        for (ts, reason) in [(20, Some("NETFILTER_DROP")), (21, None)] {
+            recorder.process_one(event(ts, reason), |e| {
+                out.push(
+                    e.get_section::<CommonEvent>(SectionId::Common)
+                        .unwrap()
+                        .timestamp,
+                );
+                Ok(())
+            })?;
+        }
+        assert_eq!(out, (9..22).collect::<Vec<u64>>());
+        assert_eq!(recorder.len(), 0);
+
+        // Size bound: all events have the same binary size.
+        let mut buf = Vec::new();
+        event(0, Some("NO_SOCKET")).to_binary(&mut buf)?;
+        let mut recorder = FlightRecorder::new(
+            RecorderLimit::Size(3 * buf.len() as u64),
+            EventFilter::from_str("skb-drop.drop_reason == NETFILTER_DROP")?,
+            Duration::ZERO,
+        );
+        for ts in 0..10 {
+            recorder.process_one(event(ts, Some("NO_SOCKET")), |_| Ok(()))?;
+        }
+        assert_eq!(recorder.len(), 3);
+        Ok(())
+    }
+}
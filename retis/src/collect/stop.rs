@@ -0,0 +1,154 @@
+//! # Stop
+//!
+//! Handles the conditions under which a collection stops on its own: after a
+//! given duration, after a given number of events or some time after an event
+//! matching a trigger was seen.
+
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::{events::*, process::filter::EventFilter};
+
+/// Conditions under which a collection should stop.
+#[derive(Default)]
+pub(crate) struct StopConditions {
+    /// Time after which the collection should stop.
+    deadline: Option<Instant>,
+    /// Number of events after which the collection should stop.
+    max_events: Option<u64>,
+    /// Number of events seen so far.
+    events: u64,
+    /// Trigger after which (+ delay) the collection should stop. Consumed once
+    /// matched.
+    trigger: Option<EventFilter>,
+    trigger_delay: Duration,
+}
+
+impl StopConditions {
+    pub(crate) fn new(
+        duration: Option<Duration>,
+        max_events: Option<u64>,
+        trigger: Option<EventFilter>,
+        trigger_delay: Duration,
+    ) -> Self {
+        Self {
+            deadline: duration.map(|d| Instant::now() + d),
+            max_events,
+            trigger,
+            trigger_delay,
+            ..Default::default()
+        }
+    }
+
+    /// Account for a collected event. Returns true if the collection should
+    /// stop right away.
+    pub(crate) fn process_one(&mut self, event: &Event) -> bool {
+        if self.trigger.as_ref().is_some_and(|t| t.matches(event)) {
+            info!(
+                "Stop trigger matched, stopping in {}s",
+                self.trigger_delay.as_secs_f64()
+            );
+
+            let deadline = Instant::now() + self.trigger_delay;
+            self.deadline = Some(match self.deadline {
+                Some(current) => current.min(deadline),
+                None => deadline,
+            });
+            self.trigger = None;
+        }
+
+        self.events += 1;
+        if self.max_events.is_some_and(|max| self.events >= max) {
+            info!("Maximum number of events reached");
+            return true;
+        }
+
+        self.expired()
+    }
+
+    /// Should the collection stop because its deadline was reached?
+    pub(crate) fn expired(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use anyhow::Result;
+
+    use super::*;
+
+    fn drop_event(reason: &str) -> Event {
+        let mut event = Event::new();
+        event
+            .insert_section(SectionId::Common, Box::<CommonEvent>::default())
+            .unwrap();
+        event
+            .insert_section(
+                SectionId::SkbDrop,
+                Box::new(SkbDropEvent {
+                    subsys: None,
+                    drop_reason: reason.to_string(),
+                }),
+            )
+            .unwrap();
+        event
+    }
+
+    #[test]
+    fn trigger() -> Result<()> {
+        let event = drop_event("NETFILTER_DROP");
+
+        for (trigger, res) in [
+            ("skb-drop", true),
+            ("skb", false),
+            ("skb-drop.drop_reason == NETFILTER_DROP", true),
+            ("skb-drop.drop_reason == NO_SOCKET", false),
+            ("skb-drop.drop_reason != NO_SOCKET", true),
+            ("common.timestamp == 0", true),
+            ("skb-drop.subsys", false),
+        ] {
+            assert_eq!(
+                EventFilter::from_str(trigger)?.matches(&event),
+                res,
+                "{trigger}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stop_conditions() -> Result<()> {
+        let mut stop = StopConditions::new(None, Some(2), None, Duration::ZERO);
+        assert!(!stop.process_one(&drop_event("NO_SOCKET")));
+        assert!(stop.process_one(&drop_event("NO_SOCKET")));
+
+        let mut stop = StopConditions::new(
+            None,
+            None,
+            Some(EventFilter::from_str(
+                "skb-drop.drop_reason == NETFILTER_DROP",
+            )?),
+            Duration::ZERO,
+        );
+        assert!(!stop.process_one(&drop_event("NO_SOCKET")));
+        assert!(!stop.expired());
+        assert!(stop.process_one(&drop_event("NETFILTER_DROP")));
+
+        let mut stop = StopConditions::new(
+            None,
+            None,
+            Some(EventFilter::from_str("skb-drop")?),
+            Duration::from_secs(3600),
+        );
+        assert!(!stop.process_one(&drop_event("NO_SOCKET")));
+        assert!(!stop.expired());
+
+        let stop = StopConditions::new(Some(Duration::ZERO), None, None, Duration::ZERO);
+        assert!(stop.expired());
+        Ok(())
+    }
+}
@@ -24,6 +24,14 @@
  */
 struct retis_global_config {
 	u8 enabled;
+	/* Keep one event out of sample_rate (disabled if 0 or 1). */
+	u32 sample_rate;
+	/* Maximum number of events per second (disabled if 0). */
+	u32 rate_limit;
+	/* Apply sampling & rate limiting per packet (skb tracking id) rather
+	 * than per probe.
+	 */
+	u8 sample_per_flow;
 };
 struct {
 	__uint(type, BPF_MAP_TYPE_HASH);
@@ -32,11 +40,15 @@ struct {
 	__type(value, struct retis_global_config);
 } global_config_map SEC(".maps");
 
-static __always_inline bool collection_enabled() {
-	struct retis_global_config *cfg;
+static __always_inline struct retis_global_config *global_config() {
 	u8 key = 0;
 
-	cfg = bpf_map_lookup_elem(&global_config_map, &key);
+	return bpf_map_lookup_elem(&global_config_map, &key);
+}
+
+static __always_inline bool collection_enabled() {
+	struct retis_global_config *cfg = global_config();
+
 	return cfg && !!cfg->enabled;
 }
 
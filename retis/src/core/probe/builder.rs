@@ -4,7 +4,7 @@
 //!
 use std::os::fd::RawFd;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 
 use crate::core::{filters::Filter, probe::*};
 
@@ -30,6 +30,10 @@ pub(super) trait ProbeBuilder {
     /// Detach all probes installed by the builder (function,
     /// tracepoint, etc).
     fn detach(&mut self) -> Result<()>;
+    /// Detach a single probe installed by the builder.
+    fn detach_probe(&mut self, probe: &Probe) -> Result<()> {
+        bail!("Detaching {probe} on its own is not supported")
+    }
 }
 
 pub(super) fn reuse_map_fds(
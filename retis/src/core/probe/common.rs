@@ -6,9 +6,17 @@ use anyhow::Result;
 use crate::core::probe::PROBE_MAX;
 
 // Please keep in sync with its BPF counterpart in bpf/include/common_defs.h
+#[derive(Default)]
 #[repr(C)]
 pub(crate) struct GlobalConfig {
     pub(crate) enabled: u8,
+    /// Keep one event out of `sample_rate` (disabled if 0 or 1).
+    pub(crate) sample_rate: u32,
+    /// Maximum number of events per second (disabled if 0).
+    pub(crate) rate_limit: u32,
+    /// Apply sampling & rate limiting per packet (skb tracking id) rather
+    /// than per probe.
+    pub(crate) sample_per_flow: u8,
 }
 unsafe impl plain::Plain for GlobalConfig {}
 
@@ -44,6 +44,36 @@ struct {
 	__uint(value_size, 127 * sizeof(u64));
 } stack_map SEC(".maps");
 
+/* Sampling & rate limiting state, per probe (indexed by the target symbol
+ * address) or global when sampling per flow (indexed by 0).
+ */
+struct retis_sampling_state {
+	/* Number of events seen, for 1/N sampling. */
+	u64 seen;
+	/* Start of the current rate limiting window. */
+	u64 window_start;
+	/* Number of events kept in the current rate limiting window. */
+	u64 window_count;
+} __binding;
+struct {
+	__uint(type, BPF_MAP_TYPE_HASH);
+	__uint(max_entries, PROBE_MAX + 1);
+	__type(key, u64);
+	__type(value, struct retis_sampling_state);
+} sampling_map SEC(".maps");
+
+/* Sampling decisions when sampling per flow, indexed by skb tracking id. */
+struct retis_sampling_flow {
+	u64 timestamp;
+	u64 orig_head;
+} __binding;
+struct {
+	__uint(type, BPF_MAP_TYPE_LRU_HASH);
+	__uint(max_entries, 8192);
+	__type(key, struct retis_sampling_flow);
+	__type(value, u8);
+} sampling_flows_map SEC(".maps");
+
 #define RETIS_F_PASS(f, v)			\
 	RETIS_F_##f##_PASS_SH = v,		\
 	RETIS_F_##f##_PASS = 1 << v
@@ -196,6 +226,81 @@ filter_outcome:
 	ctx->filters_ret |= (!!meta_filter(skb)) << RETIS_F_META_PASS_SH;
 }
 
+#define NSEC_PER_SEC	1000000000ULL
+
+static __always_inline bool sampling_keep(struct retis_sampling_state *state,
+					  struct retis_global_config *cfg,
+					  u64 now)
+{
+	if (cfg->sample_rate > 1 &&
+	    __sync_fetch_and_add(&state->seen, 1) % cfg->sample_rate)
+		return false;
+
+	if (cfg->rate_limit) {
+		/* Windows are reset without synchronization, the limit is
+		 * approximate.
+		 */
+		if (now - state->window_start >= NSEC_PER_SEC) {
+			state->window_start = now;
+			state->window_count = 0;
+		}
+		if (__sync_fetch_and_add(&state->window_count, 1) >= cfg->rate_limit)
+			return false;
+	}
+
+	return true;
+}
+
+/* Decide if an event should be reported, based on the sampling & rate limiting
+ * configuration. When sampling per flow, the decision is made the first time
+ * a packet is seen and applied to all its events.
+ */
+static __always_inline bool sample(struct retis_context *ctx)
+{
+	struct retis_sampling_state *state, zero = {};
+	struct retis_sampling_flow flow = {};
+	struct tracking_info *ti = NULL;
+	struct retis_global_config *cfg;
+	u64 key = ctx->ksym;
+	struct sk_buff *skb;
+	u8 *decision;
+	u8 keep;
+
+	cfg = global_config();
+	if (!cfg || (cfg->sample_rate <= 1 && !cfg->rate_limit))
+		return true;
+
+	if (cfg->sample_per_flow) {
+		skb = retis_get_sk_buff(ctx);
+		if (skb)
+			ti = skb_tracking_info(skb);
+		if (ti) {
+			flow.timestamp = ti->timestamp;
+			flow.orig_head = ti->orig_head;
+
+			decision = bpf_map_lookup_elem(&sampling_flows_map, &flow);
+			if (decision)
+				return !!*decision;
+
+			key = 0;
+		}
+	}
+
+	state = bpf_map_lookup_elem(&sampling_map, &key);
+	if (!state) {
+		bpf_map_update_elem(&sampling_map, &key, &zero, BPF_NOEXIST);
+		state = bpf_map_lookup_elem(&sampling_map, &key);
+		if (!state)
+			return true;
+	}
+
+	keep = sampling_keep(state, cfg, ctx->timestamp);
+	if (ti)
+		bpf_map_update_elem(&sampling_flows_map, &flow, &keep, BPF_ANY);
+
+	return keep;
+}
+
 /* The chaining function, which contains all our core probe logic. This is
  * called from each probe specific part after filling the common context and
  * just before returning.
@@ -246,6 +351,12 @@ static __always_inline int chain(struct retis_context *ctx)
 	if (nhooks == 0)
 		goto exit;
 
+	/* Apply sampling & rate limiting to events linked to packets matching
+	 * the filters, before reserving space in the events buffer.
+	 */
+	if (RETIS_TRACKABLE(ctx->filters_ret) && !sample(ctx))
+		goto exit;
+
 	event = get_event();
 	if (!event) {
 		err_report(ctx->ksym, 0);
@@ -22,6 +22,44 @@ pub(crate) fn init_config_map() -> Result<libbpf_rs::MapHandle> {
     )?)
 }
 
+#[cfg_attr(test, allow(dead_code))]
+pub(crate) fn init_sampling_map() -> Result<libbpf_rs::MapHandle> {
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        ..Default::default()
+    };
+
+    // Please keep in sync with its BPF counterpart in
+    // core/probe/kernel/bpf/include/common.h
+    Ok(libbpf_rs::MapHandle::create(
+        libbpf_rs::MapType::Hash,
+        Some("sampling_map"),
+        mem::size_of::<u64>() as u32,
+        mem::size_of::<retis_sampling_state>() as u32,
+        PROBE_MAX as u32 + 1,
+        &opts,
+    )?)
+}
+
+#[cfg_attr(test, allow(dead_code))]
+pub(crate) fn init_sampling_flows_map() -> Result<libbpf_rs::MapHandle> {
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        ..Default::default()
+    };
+
+    // Please keep in sync with its BPF counterpart in
+    // core/probe/kernel/bpf/include/common.h
+    Ok(libbpf_rs::MapHandle::create(
+        libbpf_rs::MapType::LruHash,
+        Some("sampling_flows_map"),
+        mem::size_of::<retis_sampling_flow>() as u32,
+        mem::size_of::<u8>() as u32,
+        8192,
+        &opts,
+    )?)
+}
+
 #[cfg(not(test))]
 pub(crate) fn init_stack_map() -> Result<libbpf_rs::MapHandle> {
     const MAX_STACKTRACE_ENTRIES: u32 = 256;
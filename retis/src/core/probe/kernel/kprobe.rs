@@ -4,7 +4,10 @@
 //! in two parts, the Rust code (here) and the eBPF one (bpf/kprobe.bpf.c and
 //! its auto-generated part in bpf/.out/).
 
-use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::{
+    collections::HashMap,
+    os::fd::{AsFd, AsRawFd, RawFd},
+};
 
 use anyhow::{anyhow, bail, Result};
 use libbpf_rs::skel::SkelBuilder;
@@ -19,6 +22,8 @@ use kprobe_bpf::KprobeSkelBuilder;
 #[derive(Default)]
 pub(crate) struct KprobeBuilder {
     links: Vec<libbpf_rs::Link>,
+    /// Links of the attached probes, indexed by probe key.
+    probe_links: HashMap<String, libbpf_rs::Link>,
     obj: Option<libbpf_rs::Object>,
 }
 
@@ -68,23 +73,32 @@ impl ProbeBuilder for KprobeBuilder {
             Some(obj) => obj,
             _ => bail!("Kprobe builder is uninitialized"),
         };
-        let probe = match probe.r#type() {
-            ProbeType::Kprobe(probe) => probe,
+        let kprobe = match probe.r#type() {
+            ProbeType::Kprobe(kprobe) => kprobe,
             _ => bail!("Wrong probe type {}", probe),
         };
 
-        self.links.push(
+        self.probe_links.insert(
+            probe.key(),
             obj.prog_mut("probe_kprobe")
                 .ok_or_else(|| anyhow!("Couldn't get program"))?
-                .attach_kprobe(false, probe.symbol.attach_name())?,
+                .attach_kprobe(false, kprobe.symbol.attach_name())?,
         );
         Ok(())
     }
 
     fn detach(&mut self) -> Result<()> {
+        self.probe_links.drain();
         self.links.drain(..);
         Ok(())
     }
+
+    fn detach_probe(&mut self, probe: &Probe) -> Result<()> {
+        self.probe_links
+            .remove(&probe.key())
+            .ok_or_else(|| anyhow!("No probe on {probe} is attached"))?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -8,7 +8,10 @@
 //! program into the associated kprobe that safes the context into a map which is
 //! then retrieved by the kretprobe program..
 
-use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::{
+    collections::HashMap,
+    os::fd::{AsFd, AsRawFd, RawFd},
+};
 
 use anyhow::{anyhow, bail, Result};
 use libbpf_rs::skel::SkelBuilder;
@@ -23,6 +26,8 @@ use kretprobe_bpf::KretprobeSkelBuilder;
 #[derive(Default)]
 pub(crate) struct KretprobeBuilder {
     links: Vec<libbpf_rs::Link>,
+    /// Links of the attached probes, indexed by probe key.
+    probe_links: HashMap<String, Vec<libbpf_rs::Link>>,
     obj: Option<libbpf_rs::Object>,
 }
 
@@ -73,31 +78,40 @@ impl ProbeBuilder for KretprobeBuilder {
             _ => bail!("Kretprobe builder is uninitialized"),
         };
 
-        let probe = match probe.r#type() {
-            ProbeType::Kretprobe(probe) => probe,
+        let kretprobe = match probe.r#type() {
+            ProbeType::Kretprobe(kretprobe) => kretprobe,
             _ => bail!("Wrong probe type {}", probe),
         };
 
         // Attach the kretprobe
-        self.links.push(
-            obj.prog_mut("probe_kretprobe_kretprobe")
-                .ok_or_else(|| anyhow!("Couldn't get kretprobe program"))?
-                .attach_kprobe(true, probe.symbol.attach_name())?,
-        );
+        let mut links = vec![obj
+            .prog_mut("probe_kretprobe_kretprobe")
+            .ok_or_else(|| anyhow!("Couldn't get kretprobe program"))?
+            .attach_kprobe(true, kretprobe.symbol.attach_name())?];
 
         // Attach the kprobe
-        self.links.push(
+        links.push(
             obj.prog_mut("probe_kretprobe_kprobe")
                 .ok_or_else(|| anyhow!("Couldn't get kprobe program"))?
-                .attach_kprobe(false, probe.symbol.attach_name())?,
+                .attach_kprobe(false, kretprobe.symbol.attach_name())?,
         );
+
+        self.probe_links.insert(probe.key(), links);
         Ok(())
     }
 
     fn detach(&mut self) -> Result<()> {
+        self.probe_links.drain();
         self.links.drain(..);
         Ok(())
     }
+
+    fn detach_probe(&mut self, probe: &Probe) -> Result<()> {
+        self.probe_links
+            .remove(&probe.key())
+            .ok_or_else(|| anyhow!("No probe on {probe} is attached"))?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
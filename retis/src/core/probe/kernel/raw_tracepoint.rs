@@ -5,7 +5,10 @@
 //! in two parts, the Rust code (here) and the eBPF one
 //! (bpf/raw_tracepoint.bpf.c and its auto-generated part in bpf/.out/).
 
-use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::{
+    collections::HashMap,
+    os::fd::{AsFd, AsRawFd, RawFd},
+};
 
 use anyhow::{anyhow, bail, Result};
 use libbpf_rs::skel::SkelBuilder;
@@ -21,7 +24,9 @@ use raw_tracepoint_bpf::RawTracepointSkelBuilder;
 pub(crate) struct RawTracepointBuilder {
     hooks: Vec<Hook>,
     filters: Vec<Filter>,
-    links: Vec<libbpf_rs::Link>,
+    /// Links of the attached probes (and of their hooks), indexed by probe
+    /// key.
+    links: HashMap<String, Vec<libbpf_rs::Link>>,
     obj: Option<libbpf_rs::Object>,
     map_fds: Vec<(String, RawFd)>,
 }
@@ -47,13 +52,13 @@ impl ProbeBuilder for RawTracepointBuilder {
     fn attach(&mut self, probe: &Probe) -> Result<()> {
         let mut skel = RawTracepointSkelBuilder::default().open()?;
 
-        let probe = match probe.r#type() {
-            ProbeType::RawTracepoint(probe) => probe,
+        let tp = match probe.r#type() {
+            ProbeType::RawTracepoint(tp) => tp,
             _ => bail!("Wrong probe type {}", probe),
         };
 
-        skel.rodata_mut().ksym = probe.symbol.addr()?;
-        skel.rodata_mut().nargs = probe.symbol.nargs()?;
+        skel.rodata_mut().ksym = tp.symbol.addr()?;
+        skel.rodata_mut().nargs = tp.symbol.nargs()?;
         skel.rodata_mut().nhooks = self.hooks.len() as u32;
         skel.rodata_mut().log_level = log::max_level() as u8;
 
@@ -72,16 +77,22 @@ impl ProbeBuilder for RawTracepointBuilder {
             .ok_or_else(|| anyhow!("Couldn't get program"))?;
 
         let mut links = replace_hooks(prog.as_fd().as_raw_fd(), &self.hooks)?;
-        self.links.append(&mut links);
+        links.push(prog.attach_raw_tracepoint(tp.symbol.attach_name())?);
 
-        self.links
-            .push(prog.attach_raw_tracepoint(probe.symbol.attach_name())?);
+        self.links.insert(probe.key(), links);
         self.obj = Some(obj);
         Ok(())
     }
 
     fn detach(&mut self) -> Result<()> {
-        self.links.drain(..);
+        self.links.drain();
+        Ok(())
+    }
+
+    fn detach_probe(&mut self, probe: &Probe) -> Result<()> {
+        self.links
+            .remove(&probe.key())
+            .ok_or_else(|| anyhow!("No probe on {probe} is attached"))?;
         Ok(())
     }
 }
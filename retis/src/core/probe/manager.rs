@@ -18,12 +18,20 @@ use super::{
     user::usdt,
 };
 
-use super::{common::*, kernel::config::init_config_map};
-use crate::core::{
-    filters::{self, fixup_filter_load_fn, register_filter_handler, Filter},
-    kernel::Symbol,
-    probe::user::UsdtProbe,
-    user::proc::Process,
+use super::{
+    common::*,
+    kernel::config::{init_config_map, init_sampling_flows_map, init_sampling_map},
+};
+use crate::{
+    core::{
+        events::RetisEventsFactory,
+        filters::{self, fixup_filter_load_fn, register_filter_handler, Filter},
+        kernel::Symbol,
+        probe::user::UsdtProbe,
+        user::proc::Process,
+    },
+    events::{LostEvent, SectionId},
+    helpers::time::monotonic_timestamp,
 };
 
 // Keep in sync with their BPF counterparts in bpf/include/common.h
@@ -169,6 +177,10 @@ impl ProbeManager {
             targeted_builders: Vec::new(),
             probes: HashSet::new(),
             filters: builder.filters,
+            lost_events: HashMap::new(),
+            #[cfg(not(test))]
+            probe_names: HashMap::new(),
+            lost_window_start: monotonic_timestamp()?,
         };
 
         // Install probes.
@@ -188,7 +200,10 @@ impl ProbeManager {
         {
             // Set the global config once all probes are installed, to avoid
             // inconsistencies.
-            let config = GlobalConfig { enabled: 1 };
+            let config = GlobalConfig {
+                enabled: 1,
+                ..builder.global_config
+            };
             let config = unsafe { plain::as_bytes(&config) };
             builder
                 .global_config_map
@@ -224,6 +239,14 @@ pub(crate) struct ProbeBuilderManager {
     /// Global per-probe map used to report counters.
     #[cfg(not(test))]
     counters_map: libbpf_rs::MapHandle,
+    /// Sampling & rate limiting state.
+    #[cfg(not(test))]
+    sampling_map: libbpf_rs::MapHandle,
+    /// Per-flow sampling decisions.
+    #[cfg(not(test))]
+    sampling_flows_map: libbpf_rs::MapHandle,
+    /// Global configuration, applied once all probes are installed.
+    global_config: GlobalConfig,
 }
 
 impl ProbeBuilderManager {
@@ -245,6 +268,11 @@ impl ProbeBuilderManager {
             meta_map: filters::meta::filter::init_meta_map()?,
             #[cfg(not(test))]
             counters_map: init_counters_map()?,
+            #[cfg(not(test))]
+            sampling_map: init_sampling_map()?,
+            #[cfg(not(test))]
+            sampling_flows_map: init_sampling_flows_map()?,
+            global_config: GlobalConfig::default(),
         };
 
         #[cfg(not(test))]
@@ -269,9 +297,31 @@ impl ProbeBuilderManager {
             mgr.counters_map.as_fd().as_raw_fd(),
         );
 
+        #[cfg(not(test))]
+        mgr.maps.insert(
+            "sampling_map".to_string(),
+            mgr.sampling_map.as_fd().as_raw_fd(),
+        );
+
+        #[cfg(not(test))]
+        mgr.maps.insert(
+            "sampling_flows_map".to_string(),
+            mgr.sampling_flows_map.as_fd().as_raw_fd(),
+        );
+
         Ok(mgr)
     }
 
+    /// Configure the in-kernel sampling (keep one event out of `sample_rate`)
+    /// and rate limiting (at most `rate_limit` events per second) of events,
+    /// per probe or per packet when `per_flow` is set. A value of 0 disables
+    /// the corresponding mechanism.
+    pub(crate) fn set_sampling(&mut self, sample_rate: u32, rate_limit: u32, per_flow: bool) {
+        self.global_config.sample_rate = sample_rate;
+        self.global_config.rate_limit = rate_limit;
+        self.global_config.sample_per_flow = per_flow as u8;
+    }
+
     /// Set a probe option for later fixup during the attach phase. A given
     /// option can only be set once as those are global and we can't decide
     /// which version to keep.
@@ -412,6 +462,14 @@ pub(crate) struct ProbeRuntimeManager {
     hooks: Vec<Hook>,
     probes: HashSet<String>,
     filters: Vec<Filter>,
+    /// Number of events lost per probe, as of the last lost events report.
+    lost_events: HashMap<String, u64>,
+    /// Names of the probes reported in the counters map, by (pid, symbol
+    /// address). None if the name could not be resolved.
+    #[cfg(not(test))]
+    probe_names: HashMap<(u64, u64), Option<String>>,
+    /// Start of the current lost events reporting window.
+    lost_window_start: u64,
 }
 
 impl ProbeRuntimeManager {
@@ -535,7 +593,79 @@ impl ProbeRuntimeManager {
         self.gen_generic_builders()?;
 
         let builder = self.generic_builders.get_mut(&probe.r#type_key()).unwrap();
-        Self::attach_probe(builder, &mut self.config_map, &mut self.counters_map, probe)
+        if let Err(e) =
+            Self::attach_probe(builder, &mut self.config_map, &mut self.counters_map, probe)
+        {
+            self.probes.remove(&probe.key());
+            self.forget_probe(probe)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn attach_generic_probe(&mut self, probe: &mut Probe) -> Result<()> {
+        if !self.probes.insert(probe.key()) {
+            bail!("A probe on {probe} is already attached");
+        }
+        Ok(())
+    }
+
+    /// Detach a single generic probe.
+    pub(crate) fn detach_generic_probe(&mut self, probe: &Probe) -> Result<()> {
+        if !self.probes.contains(&probe.key()) {
+            bail!("No probe on {probe} is attached");
+        }
+
+        self.generic_builders
+            .get_mut(&probe.type_key())
+            .ok_or_else(|| anyhow!("No probe on {probe} is attached"))?
+            .detach_probe(probe)?;
+
+        self.probes.remove(&probe.key());
+        self.forget_probe(probe)
+    }
+
+    /// Forget the runtime state of a kernel probe no longer attached: its
+    /// configuration, counters and lost events. This state is shared by probes
+    /// on the same symbol (e.g. a kprobe and a kretprobe) and is kept while one
+    /// of them is still attached.
+    #[cfg(not(test))]
+    fn forget_probe(&mut self, probe: &Probe) -> Result<()> {
+        let kp = match probe.r#type() {
+            ProbeType::Kprobe(kp) | ProbeType::Kretprobe(kp) | ProbeType::RawTracepoint(kp) => kp,
+            ProbeType::Usdt(_) => return Ok(()),
+        };
+
+        let symbol = kp.to_string();
+        if self
+            .probes
+            .iter()
+            .any(|p| p.split_once(':').is_some_and(|(_, s)| s == symbol))
+        {
+            return Ok(());
+        }
+
+        let (counters_key, _) = kp.gen_counters()?;
+        self.config_map
+            .delete(&counters_key.sym_addr.to_ne_bytes())
+            .ok();
+        self.counters_map
+            .delete(unsafe { plain::as_bytes(&counters_key) })
+            .ok();
+
+        if let Some(Some(name)) = self
+            .probe_names
+            .remove(&(counters_key.pid, counters_key.sym_addr))
+        {
+            self.lost_events.remove(&name);
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn forget_probe(&mut self, _: &Probe) -> Result<()> {
+        Ok(())
     }
 
     /// Get the list of all currently attached probes.
@@ -553,17 +683,65 @@ impl ProbeRuntimeManager {
             .try_for_each(|builder| builder.detach())
     }
 
-    #[cfg(test)]
-    pub(crate) fn report_counters(&self) -> Result<()> {
+    /// Emit a lost events record, through the Retis events factory, for each
+    /// probe which lost events since the previous call.
+    pub(crate) fn report_lost_events(&mut self, factory: &RetisEventsFactory) -> Result<()> {
+        let now = monotonic_timestamp()?;
+        let window_start = self.lost_window_start;
+        self.lost_window_start = now;
+
+        for (probe, total) in self.lost_events()? {
+            let prev = self.lost_events.insert(probe.clone(), total).unwrap_or(0);
+            let count = total.saturating_sub(prev);
+            if count == 0 {
+                continue;
+            }
+
+            factory.add_event(|event| {
+                event.insert_section(
+                    SectionId::Lost,
+                    Box::new(LostEvent {
+                        probe: probe.clone(),
+                        count,
+                        window_start,
+                        window_end: now,
+                    }),
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Report the events lost since the probes were attached.
+    pub(crate) fn report_counters(&mut self) -> Result<()> {
+        let mut total_lost: u64 = 0;
+
+        for (probe, lost) in self.lost_events()? {
+            warn!("lost {lost} event(s) from {probe}");
+            total_lost = total_lost.saturating_add(lost);
+        }
+
+        if total_lost > 0 {
+            warn!("total events lost: {total_lost}");
+        }
+
         Ok(())
     }
 
+    #[cfg(test)]
+    pub(crate) fn lost_events(&mut self) -> Result<HashMap<String, u64>> {
+        Ok(HashMap::new())
+    }
+
+    /// Retrieve the number of events lost per probe (kernel symbol or USDT
+    /// target) since the probes were attached. Probes which did not lose any
+    /// event, or whose name cannot be resolved, are not reported.
     #[cfg(not(test))]
-    pub(crate) fn report_counters(&self) -> Result<()> {
+    pub(crate) fn lost_events(&mut self) -> Result<HashMap<String, u64>> {
         let mut counters_key = CountersKey::default();
         let mut counters = Counters::default();
-        let mut total_lost: u64 = 0;
-        let mut proc_cache: HashMap<u64, String> = HashMap::new();
+        let mut lost = HashMap::new();
 
         for k in self.counters_map.keys() {
             counters_key
@@ -577,36 +755,41 @@ impl ProbeRuntimeManager {
                     continue;
                 }
 
-                /* kernel symbols */
-                if counters_key.pid == 0 {
-                    let ksym = Symbol::from_addr(counters_key.sym_addr)?;
-                    warn!("lost {} event(s) from {ksym}", counters.dropped_events);
-                } else {
-                    let usdt_info;
-
-                    if let Some(path) = proc_cache.get(&counters_key.pid) {
-                        usdt_info = path.to_string();
-                    } else {
-                        let proc = Process::from_pid(counters_key.pid as i32)?;
-                        let note = proc
-                            .get_note_from_symbol(counters_key.sym_addr)?
-                            .ok_or_else(|| anyhow!("Failed to get symbol information"))?;
-                        usdt_info = format!("{}:{note}", proc.path().display());
-                        proc_cache.insert(counters_key.pid, usdt_info.to_string());
-                    }
-
-                    warn!("lost {} event(s) from {usdt_info}", counters.dropped_events);
+                // Names are resolved once, as USDT targets can't be once their
+                // process exited.
+                let probe = self
+                    .probe_names
+                    .entry((counters_key.pid, counters_key.sym_addr))
+                    .or_insert_with(|| match Self::probe_name(&counters_key) {
+                        Ok(name) => Some(name),
+                        Err(e) => {
+                            warn!("Cannot resolve the probe of lost events, skipping: {e}");
+                            None
+                        }
+                    });
+
+                if let Some(probe) = probe {
+                    lost.insert(probe.clone(), counters.dropped_events);
                 }
-
-                total_lost = total_lost.saturating_add(counters.dropped_events);
             }
         }
 
-        if total_lost > 0 {
-            warn!("total events lost: {total_lost}");
+        Ok(lost)
+    }
+
+    /// Resolve the name of a probe from its counters map key.
+    #[cfg(not(test))]
+    fn probe_name(key: &CountersKey) -> Result<String> {
+        // Kernel symbols are not linked to a process, USDT targets are.
+        if key.pid == 0 {
+            return Ok(Symbol::from_addr(key.sym_addr)?.to_string());
         }
 
-        Ok(())
+        let proc = Process::from_pid(key.pid as i32)?;
+        let note = proc
+            .get_note_from_symbol(key.sym_addr)?
+            .ok_or_else(|| anyhow!("Failed to get symbol information"))?;
+        Ok(format!("{}:{note}", proc.path().display()))
     }
 }
 
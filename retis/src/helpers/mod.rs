@@ -3,4 +3,6 @@ pub(crate) mod logger;
 pub(crate) mod net;
 pub(crate) mod pager;
 pub(crate) mod signals;
+pub(crate) mod size;
+pub(crate) mod socket;
 pub(crate) mod time;
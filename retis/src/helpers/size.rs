@@ -0,0 +1,46 @@
+use anyhow::{anyhow, bail, Result};
+
+/// Parses a human readable size in bytes, e.g. "4096", "512K", "100M" or "2G".
+/// Units are powers of 1024.
+pub(crate) fn parse_size(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (value, unit) = input.split_at(split);
+
+    let value: u64 = value
+        .parse()
+        .map_err(|_| anyhow!("Invalid size '{input}'"))?;
+
+    let shift = match unit {
+        "" | "B" => 0,
+        "K" | "KB" | "KiB" => 10,
+        "M" | "MB" | "MiB" => 20,
+        "G" | "GB" | "GiB" => 30,
+        x => bail!("Invalid size unit '{x}' (valid units: K, M, G)"),
+    };
+
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| anyhow!("Size '{input}' is too large"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_from_str() {
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("512K").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("100M").unwrap(), 100 * 1024 * 1024);
+        assert_eq!(parse_size("2GiB").unwrap(), 2 * 1024 * 1024 * 1024);
+
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("1T").is_err());
+        assert!(parse_size("18446744073709551615G").is_err());
+    }
+}
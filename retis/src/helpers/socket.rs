@@ -0,0 +1,239 @@
+//! # Socket
+//!
+//! Helpers to handle the stream sockets used to send events from one Retis
+//! instance to another process.
+
+use std::{
+    fmt, fs,
+    io::{self, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use super::signals::Running;
+
+/// Address of a stream socket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum SocketAddr {
+    /// UNIX socket, given its path.
+    Unix(PathBuf),
+    /// TCP socket, given its host:port.
+    Tcp(String),
+}
+
+impl FromStr for SocketAddr {
+    type Err = anyhow::Error;
+
+    /// Parses an address following the unix:PATH or tcp:HOST:PORT format.
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.split_once(':') {
+            Some(("unix", path)) if !path.is_empty() => SocketAddr::Unix(PathBuf::from(path)),
+            Some(("tcp", addr)) if addr.contains(':') => SocketAddr::Tcp(addr.to_string()),
+            _ => bail!("Invalid socket address '{s}' (expected unix:PATH or tcp:HOST:PORT)"),
+        })
+    }
+}
+
+impl fmt::Display for SocketAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+            SocketAddr::Tcp(addr) => write!(f, "tcp:{addr}"),
+        }
+    }
+}
+
+impl SocketAddr {
+    /// Connect to a listening socket.
+    pub(crate) fn connect(&self) -> Result<SocketStream> {
+        match self {
+            SocketAddr::Unix(path) => UnixStream::connect(path).map(SocketStream::Unix),
+            SocketAddr::Tcp(addr) => TcpStream::connect(addr).map(SocketStream::Tcp),
+        }
+        .map_err(|e| anyhow!("Could not connect to {self}: {e}"))
+    }
+
+    /// Listen for incoming connections.
+    pub(crate) fn listen(&self) -> Result<SocketListener> {
+        let listener = match self {
+            SocketAddr::Unix(path) => {
+                if path.exists() {
+                    bail!("Could not listen on {self}: path already exists");
+                }
+                SocketListener::Unix(UnixListener::bind(path)?, path.clone())
+            }
+            SocketAddr::Tcp(addr) => SocketListener::Tcp(TcpListener::bind(addr)?),
+        };
+
+        match &listener {
+            SocketListener::Unix(l, _) => l.set_nonblocking(true)?,
+            SocketListener::Tcp(l) => l.set_nonblocking(true)?,
+        }
+        Ok(listener)
+    }
+}
+
+/// Connected stream socket.
+pub(crate) enum SocketStream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl SocketStream {
+    pub(crate) fn try_clone(&self) -> Result<Self> {
+        Ok(match self {
+            SocketStream::Unix(s) => SocketStream::Unix(s.try_clone()?),
+            SocketStream::Tcp(s) => SocketStream::Tcp(s.try_clone()?),
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        match self {
+            SocketStream::Unix(s) => s.set_nonblocking(nonblocking)?,
+            SocketStream::Tcp(s) => s.set_nonblocking(nonblocking)?,
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        match self {
+            SocketStream::Unix(s) => s.set_read_timeout(timeout)?,
+            SocketStream::Tcp(s) => s.set_read_timeout(timeout)?,
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        match self {
+            SocketStream::Unix(s) => s.set_write_timeout(timeout)?,
+            SocketStream::Tcp(s) => s.set_write_timeout(timeout)?,
+        }
+        Ok(())
+    }
+
+    pub(crate) fn shutdown(&self) -> Result<()> {
+        match self {
+            SocketStream::Unix(s) => s.shutdown(Shutdown::Both)?,
+            SocketStream::Tcp(s) => s.shutdown(Shutdown::Both)?,
+        }
+        Ok(())
+    }
+
+    /// Shutdown the stream once `run` is terminated, so blocking reads on the
+    /// stream return.
+    pub(crate) fn shutdown_on_termination(&self, run: &Running) -> Result<()> {
+        let stream = self.try_clone()?;
+        let run = run.clone();
+
+        thread::spawn(move || {
+            while run.running() {
+                thread::sleep(Duration::from_millis(100));
+            }
+            let _ = stream.shutdown();
+        });
+        Ok(())
+    }
+}
+
+impl Read for SocketStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            SocketStream::Unix(s) => s.read(buf),
+            SocketStream::Tcp(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for SocketStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            SocketStream::Unix(s) => s.write(buf),
+            SocketStream::Tcp(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            SocketStream::Unix(s) => s.flush(),
+            SocketStream::Tcp(s) => s.flush(),
+        }
+    }
+}
+
+/// Listening stream socket. UNIX sockets are removed from the filesystem when
+/// the listener is dropped.
+pub(crate) enum SocketListener {
+    Unix(UnixListener, PathBuf),
+    Tcp(TcpListener),
+}
+
+impl SocketListener {
+    /// Wait for an incoming connection. Returns None if `run` was terminated
+    /// before a connection was made.
+    pub(crate) fn accept(&self, run: &Running) -> Result<Option<SocketStream>> {
+        while run.running() {
+            match self.try_accept()? {
+                Some(stream) => return Ok(Some(stream)),
+                None => thread::sleep(Duration::from_millis(100)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Accept an incoming connection, if any, without waiting.
+    pub(crate) fn try_accept(&self) -> Result<Option<SocketStream>> {
+        let stream = match self {
+            SocketListener::Unix(l, _) => l.accept().map(|(s, _)| SocketStream::Unix(s)),
+            SocketListener::Tcp(l) => l.accept().map(|(s, _)| SocketStream::Tcp(s)),
+        };
+
+        match stream {
+            Ok(stream) => {
+                stream.set_nonblocking(false)?;
+                Ok(Some(stream))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Drop for SocketListener {
+    fn drop(&mut self) {
+        if let SocketListener::Unix(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_addr_from_str() {
+        assert_eq!(
+            SocketAddr::from_str("unix:/run/retis.sock").unwrap(),
+            SocketAddr::Unix(PathBuf::from("/run/retis.sock"))
+        );
+        assert_eq!(
+            SocketAddr::from_str("tcp:127.0.0.1:4242").unwrap(),
+            SocketAddr::Tcp("127.0.0.1:4242".to_string())
+        );
+        assert_eq!(
+            SocketAddr::from_str("tcp:[::1]:4242").unwrap(),
+            SocketAddr::Tcp("[::1]:4242".to_string())
+        );
+
+        assert!(SocketAddr::from_str("unix:").is_err());
+        assert!(SocketAddr::from_str("tcp:4242").is_err());
+        assert!(SocketAddr::from_str("udp:127.0.0.1:4242").is_err());
+        assert!(SocketAddr::from_str("/run/retis.sock").is_err());
+    }
+}
@@ -1,4 +1,6 @@
-use anyhow::{bail, Result};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
 use nix::time::{clock_gettime, ClockId};
 
 use crate::events::TimeSpec;
@@ -23,3 +25,50 @@ pub(crate) fn monotonic_clock_offset() -> Result<TimeSpec> {
 
     Ok(TimeSpec::new(offset.tv_sec(), offset.tv_nsec()))
 }
+
+/// Parses a human readable duration, e.g. "30s", "5m", "1h", "500ms" or "10us". A
+/// value without unit is considered to be in seconds.
+pub(crate) fn parse_duration(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (value, unit) = input.split_at(split);
+
+    let value: u64 = value
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration '{input}'"))?;
+
+    Ok(match unit {
+        "ns" => Duration::from_nanos(value),
+        "us" => Duration::from_micros(value),
+        "ms" => Duration::from_millis(value),
+        "" | "s" => Duration::from_secs(value),
+        "m" => Duration::from_secs(value * 60),
+        "h" => Duration::from_secs(value * 3600),
+        "d" => Duration::from_secs(value * 86400),
+        x => bail!("Invalid duration unit '{x}' (valid units: ns, us, ms, s, m, h, d)"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_from_str() {
+        assert_eq!(parse_duration("42").unwrap(), Duration::from_secs(42));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("10us").unwrap(), Duration::from_micros(10));
+        assert_eq!(parse_duration("100ns").unwrap(), Duration::from_nanos(100));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86400));
+
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("10w").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+}
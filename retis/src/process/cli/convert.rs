@@ -0,0 +1,115 @@
+//! # Convert
+//!
+//! Convert is a simple post-processing command converting stored events (or
+//! series) between the JSON and binary formats, and/or changing their
+//! compression.
+
+use std::{
+    fs::OpenOptions,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::{
+    cli::*,
+    events::file::{FileCompression, FileEventsFactory, FileFormat, FileType},
+    helpers::signals::Running,
+    module::Modules,
+    process::display::*,
+};
+
+/// Convert stored events between the JSON and binary formats.
+#[derive(Parser, Debug, Default)]
+#[command(name = "convert")]
+pub(crate) struct Convert {
+    /// File from which to read events.
+    #[arg(default_value = "retis.data")]
+    pub(super) input: PathBuf,
+
+    /// File to write the converted events to.
+    #[arg(short, long)]
+    pub(super) out: PathBuf,
+
+    /// Format of the output file. Defaults to the format the input file is not
+    /// using (e.g. binary for a JSON input file).
+    #[arg(long, value_enum)]
+    pub(super) format: Option<CliFileFormat>,
+
+    /// Compress the events written to the output file.
+    #[arg(long, value_enum, default_value_t = CliCompression::None)]
+    pub(super) compression: CliCompression,
+}
+
+impl SubCommandParserRunner for Convert {
+    fn run(&mut self, _: Modules) -> Result<()> {
+        // Create running instance that will handle signal termination.
+        let run = Running::new();
+        run.register_term_signals()?;
+
+        // Make sure we don't use the same file as the input one, as it would be
+        // truncated before being read.
+        if same_file(&self.input, &self.out) {
+            bail!("Cannot convert a file in-place. Please specify an output file that's different to the input one.");
+        }
+
+        // Create event factory.
+        let mut factory = FileEventsFactory::new(self.input.as_path())?;
+
+        let format = match self.format {
+            Some(format) => format.into(),
+            None => match factory.format() {
+                FileFormat::Json => FileFormat::Binary,
+                FileFormat::Binary => FileFormat::Json,
+            },
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.out)
+            .or_else(|_| bail!("Could not create or open '{}'", self.out.display()))?;
+        let writer = Box::new(BufWriter::new(
+            FileCompression::from(self.compression).writer(file)?,
+        ));
+
+        match factory.file_type() {
+            FileType::Event => {
+                let mut output = PrintEvent::new(writer, format.into());
+
+                while run.running() {
+                    match factory.next_event()? {
+                        Some(event) => output.process_one(&event)?,
+                        None => break,
+                    }
+                }
+                output.flush()?;
+            }
+            FileType::Series => {
+                let mut output = PrintSeries::new(writer, format.into());
+
+                while run.running() {
+                    match factory.next_series()? {
+                        Some(series) => output.process_one(&series)?,
+                        None => break,
+                    }
+                }
+                output.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks if two paths point to the same file. If the output one does not exist
+/// yet, they can't be the same.
+pub(super) fn same_file(input: &Path, output: &Path) -> bool {
+    match (input.canonicalize(), output.canonicalize()) {
+        (Ok(input), Ok(output)) => input == output,
+        _ => false,
+    }
+}
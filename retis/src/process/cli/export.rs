@@ -0,0 +1,159 @@
+//! # Export
+//!
+//! Export is a post-processing command converting stored events (or series) to
+//! formats understood by external tools.
+
+use std::{
+    fs::OpenOptions,
+    io::{stdout, BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Result};
+use clap::{Parser, ValueEnum};
+
+use super::{sort::SortBuffer, stats::StatsKey};
+use crate::{
+    cli::*,
+    events::file::{FileEventsFactory, FileType},
+    helpers::signals::Running,
+    module::Modules,
+    process::{
+        export::{
+            csv::{Csv, CsvField},
+            folded::FoldedStacks,
+            perfetto::PerfettoTrace,
+            Exporter,
+        },
+        flow::split_packets,
+        series::for_each_series,
+    },
+};
+
+/// Formats events can be exported to.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// Folded stacks of the events having a stack trace (see collect --stack),
+    /// to generate flame graphs.
+    #[default]
+    Folded,
+    /// Chrome trace event JSON format, to be opened in Perfetto. Events are
+    /// sorted in series (if needed), each packet being shown as a track.
+    Perfetto,
+    /// Comma separated values of selected fields (see --fields), one row per
+    /// event.
+    Csv,
+}
+
+/// Export stored events to formats understood by external tools.
+#[derive(Parser, Debug, Default)]
+#[command(name = "export")]
+pub(crate) struct Export {
+    /// File from which to read events.
+    #[arg(default_value = "retis.data")]
+    pub(super) input: PathBuf,
+
+    /// Format to export the events to.
+    #[arg(long, value_enum)]
+    pub(super) format: ExportFormat,
+
+    /// Write the exported data to a file rather than to stdout.
+    #[arg(short, long)]
+    pub(super) out: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub(super) buffer: SortBuffer,
+
+    /// Comma separated list of keys whose values are added as root frames of
+    /// the folded stacks (e.g. "symbol,drop-reason"). Supported keys: symbol,
+    /// dev, netns, drop-reason, nft-chain, nft-verdict, ct-state.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub(super) stack_keys: Vec<StatsKey>,
+
+    /// Keep the offsets of the symbols in the folded stacks.
+    #[arg(long)]
+    pub(super) stack_offsets: bool,
+
+    /// Comma separated list of the fields exported as CSV columns, given by
+    /// their path (e.g. "common.timestamp,skb.ip.saddr,skb_drop.drop_reason").
+    #[arg(long, value_delimiter = ',', required_if_eq("format", "csv"))]
+    pub(super) fields: Vec<CsvField>,
+}
+
+impl Export {
+    fn exporter(&self, writer: Box<dyn Write>, series: bool) -> Box<dyn Exporter> {
+        match self.format {
+            ExportFormat::Folded => Box::new(FoldedStacks::new(
+                writer,
+                self.stack_keys.clone(),
+                self.stack_offsets,
+            )),
+            ExportFormat::Perfetto => Box::new(PerfettoTrace::new(writer)),
+            ExportFormat::Csv => Box::new(Csv::new(writer, self.fields.clone(), series)),
+        }
+    }
+}
+
+impl SubCommandParserRunner for Export {
+    fn run(&mut self, _: Modules) -> Result<()> {
+        // Create running instance that will handle signal termination.
+        let run = Running::new();
+        run.register_term_signals()?;
+
+        // Create event factory.
+        let mut factory = FileEventsFactory::new(self.input.as_path())?;
+
+        let writer: Box<dyn Write> = match &self.out {
+            Some(out) => Box::new(BufWriter::new(
+                OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(out)
+                    .or_else(|_| bail!("Could not create or open '{}'", out.display()))?,
+            )),
+            None => Box::new(stdout()),
+        };
+        let series = matches!(factory.file_type(), FileType::Series);
+        let mut exporter = self.exporter(writer, series);
+
+        // Formats requiring series. Series holding multiple packets (e.g. when
+        // sorted by flow) are split, so each packet gets its own track.
+        if self.format == ExportFormat::Perfetto {
+            let mut idx = 0;
+            for_each_series(&mut factory, self.buffer.max_buffer, &run, |series| {
+                split_packets(&series).into_iter().try_for_each(|packet| {
+                    exporter.process(packet, Some(idx))?;
+                    idx += 1;
+                    Ok(())
+                })
+            })?;
+            return exporter.finish();
+        }
+
+        match factory.file_type() {
+            FileType::Event => {
+                while run.running() {
+                    match factory.next_event()? {
+                        Some(event) => exporter.process(&[event], None)?,
+                        None => break,
+                    }
+                }
+            }
+            FileType::Series => {
+                let mut idx = 0;
+                while run.running() {
+                    match factory.next_series()? {
+                        Some(series) => {
+                            exporter.process(&series.events, Some(idx))?;
+                            idx += 1;
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        exporter.finish()
+    }
+}
@@ -0,0 +1,116 @@
+//! # Filter
+//!
+//! Filter is a post-processing command writing stored events (or series)
+//! matching a filter expression to a new, reduced, file.
+
+use std::{fs::OpenOptions, io::BufWriter, path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::{
+    cli::*,
+    events::file::{FileCompression, FileEventsFactory, FileType},
+    helpers::signals::Running,
+    module::Modules,
+    process::{display::*, filter::EventFilter},
+};
+
+use super::convert::same_file;
+
+/// Write stored events matching a filter expression to a new file.
+///
+/// Fields are addressed by their path (e.g. "skb.ip.daddr") and compared to
+/// values using ==, !=, <, <=, >, >= or =~ and !~ (glob matching).
+/// Comparisons can be combined using &&, || and ! and grouped using
+/// parenthesis. A path without comparison matches events in which the field
+/// is set. When filtering series, series having at least one matching event
+/// are kept.
+#[derive(Parser, Debug, Default)]
+#[command(name = "filter")]
+pub(crate) struct FilterCmd {
+    /// Filter expression.
+    ///
+    /// Example: 'skb.ip.daddr == "10.0.0.1" && skb-drop.drop_reason == NETFILTER_DROP'
+    pub(super) filter: String,
+
+    /// File from which to read events.
+    #[arg(default_value = "retis.data")]
+    pub(super) input: PathBuf,
+
+    /// File to write the matching events to.
+    #[arg(short, long)]
+    pub(super) out: PathBuf,
+
+    /// Format of the output file. Defaults to the format of the input file.
+    #[arg(long, value_enum)]
+    pub(super) format: Option<CliFileFormat>,
+
+    /// Compress the events written to the output file.
+    #[arg(long, value_enum, default_value_t = CliCompression::None)]
+    pub(super) compression: CliCompression,
+}
+
+impl SubCommandParserRunner for FilterCmd {
+    fn run(&mut self, _: Modules) -> Result<()> {
+        // Create running instance that will handle signal termination.
+        let run = Running::new();
+        run.register_term_signals()?;
+
+        let filter = EventFilter::from_str(&self.filter)?;
+
+        // Make sure we don't use the same file as the input one, as it would be
+        // truncated before being read.
+        if same_file(&self.input, &self.out) {
+            bail!("Cannot filter a file in-place. Please specify an output file that's different to the input one.");
+        }
+
+        // Create event factory.
+        let mut factory = FileEventsFactory::new(self.input.as_path())?;
+        let format = match self.format {
+            Some(format) => format.into(),
+            None => factory.format(),
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.out)
+            .or_else(|_| bail!("Could not create or open '{}'", self.out.display()))?;
+        let writer = Box::new(BufWriter::new(
+            FileCompression::from(self.compression).writer(file)?,
+        ));
+
+        match factory.file_type() {
+            FileType::Event => {
+                let mut output = PrintEvent::new(writer, format.into());
+
+                while run.running() {
+                    match factory.next_event()? {
+                        Some(event) if filter.matches(&event) => output.process_one(&event)?,
+                        Some(_) => continue,
+                        None => break,
+                    }
+                }
+                output.flush()?;
+            }
+            FileType::Series => {
+                let mut output = PrintSeries::new(writer, format.into());
+
+                while run.running() {
+                    match factory.next_series()? {
+                        Some(series) if filter.matches_series(&series) => {
+                            output.process_one(&series)?
+                        }
+                        Some(_) => continue,
+                        None => break,
+                    }
+                }
+                output.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+//! # Graph
+//!
+//! Graph is a post-processing command building a directed graph of the paths
+//! packets take through the stack, from their series, and writing it in the
+//! DOT format (Graphviz).
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{stdout, BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use super::{
+    latency::{format_ns, percentile},
+    sort::SortBuffer,
+    stats::StatsKey,
+};
+use crate::{
+    cli::*,
+    events::{file::FileEventsFactory, *},
+    helpers::signals::Running,
+    module::Modules,
+    process::{filter::EventFilter, flow::split_packets, series::for_each_series},
+};
+
+/// Nftables verdicts ending the processing of a packet.
+const NFT_TERMINAL_VERDICTS: [&str; 4] = ["drop", "accept", "stolen", "queue"];
+
+/// Kind of the graph nodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NodeKind {
+    /// Kernel or USDT symbol.
+    Probe,
+    /// Packet drop, from the skb-drop section.
+    Drop,
+    /// Nftables verdict.
+    Verdict,
+}
+
+/// Transition between two nodes.
+#[derive(Default)]
+struct Edge {
+    /// Number of packets taking the transition.
+    packets: u64,
+    /// Latencies of the transition, in nanoseconds. Empty for transitions to
+    /// terminal nodes.
+    latencies: Vec<u64>,
+}
+
+/// Directed graph of probe transitions.
+#[derive(Default)]
+pub(crate) struct PathGraph {
+    /// Nodes, in order of appearance.
+    nodes: Vec<(String, NodeKind)>,
+    index: HashMap<String, usize>,
+    edges: HashMap<(usize, usize), Edge>,
+    /// Number of packet series processed.
+    series: u64,
+}
+
+impl PathGraph {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    fn node(&mut self, name: String, kind: NodeKind) -> usize {
+        match self.index.get(&name) {
+            Some(idx) => *idx,
+            None => {
+                self.index.insert(name.clone(), self.nodes.len());
+                self.nodes.push((name, kind));
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Add the paths of the packets of a series to the graph. Series holding
+    /// multiple packets (e.g. when sorted by flow) are split, so transitions
+    /// are only made between probes of the same packet.
+    pub(crate) fn process_series(&mut self, series: &EventSeries) {
+        split_packets(series)
+            .into_iter()
+            .for_each(|packet| self.process_packet(packet));
+    }
+
+    /// Add the path of a packet to the graph.
+    fn process_packet(&mut self, events: &[Event]) {
+        let mut seen = HashSet::new();
+        let mut prev: Option<(usize, u64)> = None;
+
+        for event in events.iter() {
+            let (symbol, timestamp) = match (
+                StatsKey::Symbol.value(event),
+                event.get_section::<CommonEvent>(SectionId::Common),
+            ) {
+                (Some(symbol), Some(common)) => (symbol, common.timestamp),
+                _ => continue,
+            };
+            let node = self.node(symbol, NodeKind::Probe);
+
+            if let Some((from, start)) = prev {
+                let edge = self.edges.entry((from, node)).or_default();
+                edge.latencies.push(timestamp.saturating_sub(start));
+                if seen.insert((from, node)) {
+                    edge.packets += 1;
+                }
+            }
+            prev = Some((node, timestamp));
+
+            // Drops and final verdicts are terminal nodes.
+            let mut terminals = Vec::new();
+            if let Some(reason) = StatsKey::DropReason.value(event) {
+                terminals.push((format!("drop {reason}"), NodeKind::Drop));
+            }
+            if let Some(nft) = event.get_section::<NftEvent>(SectionId::Nft) {
+                if NFT_TERMINAL_VERDICTS.contains(&nft.verdict.as_str()) {
+                    terminals.push((
+                        format!(
+                            "nft {} ({}/{})",
+                            nft.verdict, nft.table_name, nft.chain_name
+                        ),
+                        NodeKind::Verdict,
+                    ));
+                }
+            }
+            for (name, kind) in terminals.into_iter() {
+                let terminal = self.node(name, kind);
+                if seen.insert((node, terminal)) {
+                    self.edges.entry((node, terminal)).or_default().packets += 1;
+                }
+            }
+        }
+
+        if prev.is_some() {
+            self.series += 1;
+        }
+    }
+
+    /// Write the graph in the DOT format. Edges are labeled with the number
+    /// of packets taking them and their median latency, their width being
+    /// proportional to the number of packets.
+    pub(crate) fn write_dot<W: Write>(&self, w: &mut W) -> Result<()> {
+        writeln!(w, "digraph retis {{")?;
+        writeln!(w, "  label=\"{} series\";", self.series)?;
+        writeln!(w, "  node [shape=box];")?;
+
+        for (idx, (name, kind)) in self.nodes.iter().enumerate() {
+            let style = match kind {
+                NodeKind::Probe => "",
+                NodeKind::Drop => ", shape=octagon, color=red",
+                NodeKind::Verdict => ", shape=diamond",
+            };
+            writeln!(w, "  n{idx} [label=\"{}\"{style}];", escape(name))?;
+        }
+
+        let max = self.edges.values().map(|e| e.packets).max().unwrap_or(1);
+        let mut edges: Vec<_> = self.edges.iter().collect();
+        edges.sort_by_key(|((from, to), _)| (*from, *to));
+
+        for ((from, to), edge) in edges.iter() {
+            let mut label = format!("{}", edge.packets);
+            if !edge.latencies.is_empty() {
+                let mut latencies = edge.latencies.clone();
+                latencies.sort_unstable();
+                label.push_str(&format!(" ({})", format_ns(percentile(&latencies, 50))));
+            }
+
+            writeln!(
+                w,
+                "  n{from} -> n{to} [label=\"{label}\", penwidth={:.1}];",
+                1.0 + 4.0 * edge.packets as f64 / max as f64
+            )?;
+        }
+
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+}
+
+/// Escape a string for use in a DOT quoted identifier.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Build a graph of the paths packets take through the stack.
+///
+/// Reads events (or series) from the INPUT file, sorts them in series using
+/// their tracking information (if needed) and writes a directed graph of the
+/// probe transitions in the DOT format. Drops and final nftables verdicts are
+/// shown as terminal nodes.
+///
+/// Example: retis graph | dot -Tsvg > paths.svg
+#[derive(Parser, Debug, Default)]
+#[command(name = "graph")]
+pub(crate) struct Graph {
+    /// File from which to read events.
+    #[arg(default_value = "retis.data")]
+    pub(super) input: PathBuf,
+
+    #[command(flatten)]
+    pub(super) buffer: SortBuffer,
+
+    /// Write the graph to a file rather than to stdout.
+    #[arg(short, long)]
+    pub(super) out: Option<PathBuf>,
+
+    /// Only use series having at least one event matching a filter
+    /// expression.
+    ///
+    /// Example: --filter "skb.tcp.dport == 80"
+    #[arg(long)]
+    pub(super) filter: Option<EventFilter>,
+}
+
+impl SubCommandParserRunner for Graph {
+    fn run(&mut self, _: Modules) -> Result<()> {
+        // Create running instance that will handle signal termination.
+        let run = Running::new();
+        run.register_term_signals()?;
+
+        // Create event factory.
+        let mut factory = FileEventsFactory::new(self.input.as_path())?;
+        let mut graph = PathGraph::new();
+
+        for_each_series(&mut factory, self.buffer.max_buffer, &run, |series| {
+            if !self
+                .filter
+                .as_ref()
+                .is_some_and(|f| !f.matches_series(&series))
+            {
+                graph.process_series(&series);
+            }
+            Ok(())
+        })?;
+
+        match &self.out {
+            Some(out) => {
+                let file = File::create(out)
+                    .or_else(|_| bail!("Could not create or open '{}'", out.display()))?;
+                let mut writer = BufWriter::new(file);
+                graph.write_dot(&mut writer)?;
+                writer.flush()?;
+            }
+            None => graph.write_dot(&mut stdout())?,
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tests::TestEvent;
+
+    fn event(id: u64, symbol: &str, timestamp: u64) -> TestEvent {
+        TestEvent::new()
+            .common(timestamp)
+            .kernel(symbol, "kprobe")
+            .tracking(id, id, 0)
+    }
+
+    #[test]
+    fn graph() -> Result<()> {
+        let mut graph = PathGraph::new();
+        // Paths are followed per packet, even within a flow series.
+        graph.process_series(&EventSeries {
+            events: vec![
+                event(1, "ip_rcv", 100).build(),
+                event(1, "tcp_v4_rcv", 200).build(),
+                event(1, "skb:kfree_skb", 300).drop("NO_SOCKET").build(),
+                event(2, "ip_rcv", 1000).build(),
+                event(2, "tcp_v4_rcv", 1400).build(),
+            ],
+        });
+
+        let mut out = Vec::new();
+        graph.write_dot(&mut out)?;
+        let out = String::from_utf8(out)?;
+
+        assert!(out.starts_with("digraph retis {"));
+        assert!(out.contains("label=\"2 series\""));
+        assert!(out.contains("n0 [label=\"ip_rcv\"];"));
+        assert!(out.contains("n3 [label=\"drop NO_SOCKET\", shape=octagon, color=red];"));
+        assert!(out.contains("n0 -> n1 [label=\"2 (100ns)\", penwidth=5.0];"));
+        assert!(out.contains("n1 -> n2 [label=\"1 (100ns)\", penwidth=3.0];"));
+        assert!(out.contains("n2 -> n3 [label=\"1\", penwidth=3.0];"));
+        Ok(())
+    }
+}
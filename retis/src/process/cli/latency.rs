@@ -0,0 +1,412 @@
+//! # Latency
+//!
+//! Latency is a post-processing command computing the time spent by packets
+//! between consecutive probes (hops) of their series, and reporting
+//! percentiles, histograms and outliers for each hop.
+
+use std::{
+    collections::HashMap,
+    io::{stdout, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::Result;
+use clap::Parser;
+use serde_json::json;
+
+use super::{
+    sort::SortBuffer,
+    stats::{StatsFormat, StatsKey},
+};
+use crate::{
+    cli::*,
+    events::{file::FileEventsFactory, *},
+    helpers::{signals::Running, time::parse_duration},
+    module::Modules,
+    process::{flow::split_packets, series::for_each_series},
+};
+
+/// Percentiles reported for each hop.
+const PERCENTILES: [u32; 3] = [50, 90, 99];
+/// Width of the histogram bars, in characters.
+const HISTOGRAM_WIDTH: u64 = 40;
+
+/// Hop latency above the outlier threshold.
+struct Outlier {
+    tracking_id: u128,
+    from: String,
+    to: String,
+    latency: u64,
+}
+
+/// Latency between consecutive probes of packet series.
+pub(crate) struct LatencyStats {
+    /// Latencies of each (from, to) hop, in nanoseconds.
+    hops: HashMap<(String, String), Vec<u64>>,
+    /// Number of packet series processed.
+    series: u64,
+    /// Hop latency above which a packet is reported, in nanoseconds.
+    threshold: Option<u64>,
+    outliers: Vec<Outlier>,
+}
+
+impl LatencyStats {
+    pub(crate) fn new(threshold: Option<Duration>) -> Self {
+        Self {
+            hops: HashMap::new(),
+            series: 0,
+            threshold: threshold.map(|t| t.as_nanos() as u64),
+            outliers: Vec::new(),
+        }
+    }
+
+    /// Account for the hops of a series. Series holding multiple packets
+    /// (e.g. when sorted by flow) are split, as hops are only computed between
+    /// probes of the same packet.
+    pub(crate) fn process_series(&mut self, series: &EventSeries) {
+        split_packets(series)
+            .into_iter()
+            .for_each(|packet| self.process_packet(packet));
+    }
+
+    /// Account for the hops of a packet. Events not generated by a probe (e.g.
+    /// startup events) are ignored.
+    fn process_packet(&mut self, events: &[Event]) {
+        let hits: Vec<(String, u64)> = events
+            .iter()
+            .filter_map(|event| {
+                Some((
+                    StatsKey::Symbol.value(event)?,
+                    event
+                        .get_section::<CommonEvent>(SectionId::Common)?
+                        .timestamp,
+                ))
+            })
+            .collect();
+        if hits.len() < 2 {
+            return;
+        }
+        self.series += 1;
+
+        let tracking_id = events
+            .iter()
+            .find_map(|e| e.get_section::<TrackingInfo>(SectionId::Tracking))
+            .map(|t| t.skb.tracking_id());
+
+        for hop in hits.windows(2) {
+            let ((from, start), (to, end)) = (&hop[0], &hop[1]);
+            let latency = end.saturating_sub(*start);
+
+            self.hops
+                .entry((from.clone(), to.clone()))
+                .or_default()
+                .push(latency);
+
+            if let (Some(threshold), Some(tracking_id)) = (self.threshold, tracking_id) {
+                if latency > threshold {
+                    self.outliers.push(Outlier {
+                        tracking_id,
+                        from: from.clone(),
+                        to: to.clone(),
+                        latency,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Get the hops with their sorted latencies, most frequent first.
+    fn sorted(&self) -> Vec<(&(String, String), Vec<u64>)> {
+        let mut hops: Vec<_> = self
+            .hops
+            .iter()
+            .map(|(hop, latencies)| {
+                let mut latencies = latencies.clone();
+                latencies.sort_unstable();
+                (hop, latencies)
+            })
+            .collect();
+        hops.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(b.0)));
+        hops
+    }
+
+    pub(crate) fn to_json(&self, histogram: bool) -> serde_json::Value {
+        let outliers: Vec<_> = self
+            .outliers
+            .iter()
+            .map(|o| {
+                json!({
+                    "tracking_id": format!("{:x}", o.tracking_id),
+                    "from": o.from,
+                    "to": o.to,
+                    "latency": o.latency,
+                })
+            })
+            .collect();
+        let series = self.series;
+
+        let hops: Vec<_> = self
+            .sorted()
+            .iter()
+            .map(|((from, to), latencies)| {
+                let mut hop = json!({
+                    "from": from,
+                    "to": to,
+                    "count": latencies.len(),
+                    "min": latencies[0],
+                    "max": latencies[latencies.len() - 1],
+                });
+                for p in PERCENTILES.iter() {
+                    hop[format!("p{p}")] = json!(percentile(latencies, *p));
+                }
+                if histogram {
+                    hop["histogram"] = log2_histogram(latencies)
+                        .iter()
+                        .map(|(low, high, count)| json!({"low": low, "high": high, "count": count}))
+                        .collect();
+                }
+                hop
+            })
+            .collect();
+
+        json!({
+            "series": series,
+            "hops": hops,
+            "outliers": outliers,
+        })
+    }
+
+    pub(crate) fn write_text<W: Write>(&self, w: &mut W, histogram: bool) -> Result<()> {
+        writeln!(w, "{} series", self.series)?;
+
+        let hops = self.sorted();
+        let width = hops
+            .iter()
+            .map(|((from, to), _)| from.len() + to.len() + 4)
+            .max()
+            .unwrap_or_default()
+            .max("hop".len());
+
+        writeln!(w)?;
+        write!(w, "{:<width$}  {:>8}  {:>9}", "hop", "count", "min")?;
+        for p in PERCENTILES.iter() {
+            write!(w, "  {:>9}", format!("p{p}"))?;
+        }
+        writeln!(w, "  {:>9}", "max")?;
+
+        for ((from, to), latencies) in hops.iter() {
+            write!(
+                w,
+                "{:<width$}  {:>8}  {:>9}",
+                format!("{from} -> {to}"),
+                latencies.len(),
+                format_ns(latencies[0]),
+            )?;
+            for p in PERCENTILES.iter() {
+                write!(w, "  {:>9}", format_ns(percentile(latencies, *p)))?;
+            }
+            writeln!(w, "  {:>9}", format_ns(latencies[latencies.len() - 1]))?;
+        }
+
+        if histogram {
+            for ((from, to), latencies) in hops.iter() {
+                writeln!(w, "\n{from} -> {to}")?;
+
+                let buckets = log2_histogram(latencies);
+                let max = buckets.iter().map(|b| b.2).max().unwrap_or(1);
+                for (low, high, count) in buckets.iter() {
+                    writeln!(
+                        w,
+                        "  {:>20}  {count:>8} |{}",
+                        format!("[{}, {})", format_ns(*low), format_ns(*high)),
+                        "*".repeat((count * HISTOGRAM_WIDTH).div_ceil(max) as usize),
+                    )?;
+                }
+            }
+        }
+
+        if let Some(threshold) = self.threshold {
+            writeln!(
+                w,
+                "\n{} hop(s) above {}",
+                self.outliers.len(),
+                format_ns(threshold)
+            )?;
+            for o in self.outliers.iter() {
+                writeln!(
+                    w,
+                    "  #{:x} {} -> {} {}",
+                    o.tracking_id,
+                    o.from,
+                    o.to,
+                    format_ns(o.latency)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get a percentile of sorted values, using the nearest-rank method.
+pub(super) fn percentile(sorted: &[u64], p: u32) -> u64 {
+    let rank = (sorted.len() * p as usize).div_ceil(100);
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Build a histogram of sorted values using power of two buckets. Returns the
+/// non-empty buckets as (low, high, count), `high` being excluded.
+fn log2_histogram(sorted: &[u64]) -> Vec<(u64, u64, u64)> {
+    let mut buckets: Vec<(u64, u64, u64)> = Vec::new();
+
+    for value in sorted.iter() {
+        let (low, high) = match value {
+            0 => (0, 1),
+            v => {
+                let low = 1u64 << (63 - v.leading_zeros());
+                (low, low.saturating_mul(2))
+            }
+        };
+
+        match buckets.last_mut() {
+            Some(bucket) if bucket.0 == low => bucket.2 += 1,
+            _ => buckets.push((low, high, 1)),
+        }
+    }
+
+    buckets
+}
+
+/// Format a duration given in nanoseconds in a human readable way.
+pub(super) fn format_ns(ns: u64) -> String {
+    match ns {
+        0..=999 => format!("{ns}ns"),
+        1_000..=999_999 => format!("{:.1}us", ns as f64 / 1e3),
+        1_000_000..=999_999_999 => format!("{:.1}ms", ns as f64 / 1e6),
+        _ => format!("{:.2}s", ns as f64 / 1e9),
+    }
+}
+
+/// Compute the latency between consecutive probes of packet series.
+///
+/// Reads events (or series) from the INPUT file, sorts them in series using
+/// their tracking information (if needed) and computes the time spent between
+/// each consecutive probes, reported per hop (probe A -> probe B).
+#[derive(Parser, Debug, Default)]
+#[command(name = "latency")]
+pub(crate) struct Latency {
+    /// File from which to read events.
+    #[arg(default_value = "retis.data")]
+    pub(super) input: PathBuf,
+
+    #[command(flatten)]
+    pub(super) buffer: SortBuffer,
+
+    /// Report the packets having a hop latency above a given duration (e.g.
+    /// "500us", "2ms").
+    #[arg(long, value_parser = parse_duration)]
+    pub(super) outliers: Option<Duration>,
+
+    /// Display a latency histogram for each hop.
+    #[arg(long)]
+    pub(super) histogram: bool,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+    pub(super) format: StatsFormat,
+}
+
+impl SubCommandParserRunner for Latency {
+    fn run(&mut self, _: Modules) -> Result<()> {
+        // Create running instance that will handle signal termination.
+        let run = Running::new();
+        run.register_term_signals()?;
+
+        // Create event factory.
+        let mut factory = FileEventsFactory::new(self.input.as_path())?;
+        let mut stats = LatencyStats::new(self.outliers);
+
+        for_each_series(&mut factory, self.buffer.max_buffer, &run, |series| {
+            stats.process_series(&series);
+            Ok(())
+        })?;
+
+        match self.format {
+            StatsFormat::Text => stats.write_text(&mut stdout(), self.histogram)?,
+            StatsFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&stats.to_json(self.histogram))?
+            ),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tests::TestEvent;
+
+    fn series(id: u64, hits: &[(&str, u64)]) -> EventSeries {
+        let events = hits
+            .iter()
+            .map(|(symbol, timestamp)| {
+                TestEvent::new()
+                    .common(*timestamp)
+                    .kernel(symbol, "kprobe")
+                    .tracking(id, id, 0)
+                    .build()
+            })
+            .collect();
+
+        EventSeries { events }
+    }
+
+    #[test]
+    fn helpers() {
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&sorted, 50), 50);
+        assert_eq!(percentile(&sorted, 99), 99);
+        assert_eq!(percentile(&[42], 90), 42);
+
+        assert_eq!(
+            log2_histogram(&[0, 1, 5, 6, 7, 1000]),
+            vec![(0, 1, 1), (1, 2, 1), (4, 8, 3), (512, 1024, 1)]
+        );
+
+        assert_eq!(format_ns(999), "999ns");
+        assert_eq!(format_ns(1500), "1.5us");
+        assert_eq!(format_ns(2_000_000), "2.0ms");
+    }
+
+    #[test]
+    fn latency() -> Result<()> {
+        let mut stats = LatencyStats::new(Some(Duration::from_nanos(500)));
+        // Two packets in a single series, as grouped by sort --group-by flow.
+        let mut flow = series(1, &[("ip_rcv", 100), ("tcp_v4_rcv", 200)]);
+        flow.events
+            .extend(series(2, &[("ip_rcv", 300), ("tcp_v4_rcv", 1300)]).events);
+        stats.process_series(&flow);
+        stats.process_series(&series(3, &[("ip_rcv", 400)]));
+
+        let json = stats.to_json(false);
+        assert_eq!(json["series"], 2);
+        assert_eq!(json["hops"][0]["from"], "ip_rcv");
+        assert_eq!(json["hops"][0]["to"], "tcp_v4_rcv");
+        assert_eq!(json["hops"][0]["count"], 2);
+        assert_eq!(json["hops"][0]["min"], 100);
+        assert_eq!(json["hops"][0]["p50"], 100);
+        assert_eq!(json["hops"][0]["max"], 1000);
+        assert_eq!(json["outliers"][0]["tracking_id"], "20000000000000002");
+        assert_eq!(json["outliers"].as_array().unwrap().len(), 1);
+
+        let mut out = Vec::new();
+        stats.write_text(&mut out, true)?;
+        let out = String::from_utf8(out)?;
+        assert!(out.contains("ip_rcv -> tcp_v4_rcv"));
+        assert!(out.contains("1 hop(s) above 500ns"));
+        Ok(())
+    }
+}
@@ -0,0 +1,284 @@
+//! # Merge
+//!
+//! Merge is a post-processing command combining events stored in multiple
+//! files, e.g. collected on different hosts or during different runs, into a
+//! single time-ordered file. Timestamps are converted to wall-clock time using
+//! the startup event of each file and events are tagged with their origin.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::OpenOptions,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+use crate::{
+    cli::*,
+    events::{
+        file::{FileCompression, FileEventsFactory, FileFormat, FileType},
+        *,
+    },
+    helpers::signals::Running,
+    module::Modules,
+    process::display::*,
+};
+
+use super::convert::same_file;
+
+/// Merge events from multiple files into a single time-ordered file.
+#[derive(Parser, Debug, Default)]
+#[command(name = "merge")]
+pub(crate) struct Merge {
+    /// Files from which to read events, following the PATH[:LABEL] format. The
+    /// label identifies the origin of the events in the merged file (e.g. a
+    /// host name) and defaults to the file name.
+    #[arg(required = true)]
+    pub(super) inputs: Vec<String>,
+
+    /// File to write the merged events to.
+    #[arg(short, long)]
+    pub(super) out: PathBuf,
+
+    /// Format of the output file.
+    #[arg(long, value_enum, default_value_t = CliFileFormat::Json)]
+    pub(super) format: CliFileFormat,
+
+    /// Compress the events written to the output file.
+    #[arg(long, value_enum, default_value_t = CliCompression::None)]
+    pub(super) compression: CliCompression,
+}
+
+/// Input file being merged.
+struct MergeInput {
+    factory: FileEventsFactory,
+    label: String,
+    /// Offset to add to the monotonic timestamps of the file to convert them
+    /// to wall-clock ones, in nanoseconds.
+    offset: i64,
+}
+
+impl MergeInput {
+    fn new(path: &Path, label: String) -> Result<Self> {
+        let mut factory = FileEventsFactory::new(path)?;
+        if matches!(factory.file_type(), FileType::Series) {
+            bail!(
+                "Cannot merge {}: only event files can be merged, not sorted ones",
+                path.display()
+            );
+        }
+
+        // Collections start with a startup event, holding the clock offset
+        // needed to convert timestamps.
+        let offset = match factory
+            .next_event()?
+            .as_ref()
+            .and_then(|e| e.get_section::<StartupEvent>(SectionId::Startup))
+        {
+            Some(startup) => {
+                let offset = startup.clock_monotonic_offset;
+                offset.sec() * 1_000_000_000 + offset.nsec()
+            }
+            None => bail!(
+                "Cannot merge {}: no startup event found at the beginning of the file",
+                path.display()
+            ),
+        };
+
+        Ok(Self {
+            factory,
+            label,
+            offset,
+        })
+    }
+
+    /// Get the next event of the input, converted to wall-clock time and tagged
+    /// with its origin, along with its timestamp.
+    fn next_event(&mut self) -> Result<Option<(u64, Event)>> {
+        loop {
+            let mut event = match self.factory.next_event()? {
+                Some(event) => event,
+                None => return Ok(None),
+            };
+
+            // Startup events of the inputs are replaced by the one of the
+            // merged file.
+            if event
+                .get_section::<StartupEvent>(SectionId::Startup)
+                .is_some()
+            {
+                continue;
+            }
+
+            let timestamp = to_wall_clock(&mut event, self.offset)?;
+            event.insert_section(
+                SectionId::Origin,
+                Box::new(OriginEvent {
+                    label: self.label.clone(),
+                }),
+            )?;
+
+            return Ok(Some((timestamp, event)));
+        }
+    }
+}
+
+/// Parse an input following the PATH[:LABEL] format.
+fn parse_input(input: &str) -> (PathBuf, String) {
+    match input.rsplit_once(':') {
+        Some((path, label)) if !path.is_empty() && !label.is_empty() => {
+            (PathBuf::from(path), label.to_string())
+        }
+        _ => {
+            let path = PathBuf::from(input);
+            let label = path
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_else(|| input.to_string());
+            (path, label)
+        }
+    }
+}
+
+/// Convert the timestamps of an event to wall-clock time, given the offset of
+/// its monotonic clock. Returns the converted event timestamp.
+fn to_wall_clock(event: &mut Event, offset: i64) -> Result<u64> {
+    let convert = |ts: u64| (ts as i64).saturating_add(offset).max(0) as u64;
+
+    if let Some(lost) = event.get_section_mut::<LostEvent>(SectionId::Lost) {
+        lost.window_start = convert(lost.window_start);
+        lost.window_end = convert(lost.window_end);
+    }
+
+    match event.get_section_mut::<CommonEvent>(SectionId::Common) {
+        Some(common) => {
+            common.timestamp = convert(common.timestamp);
+            Ok(common.timestamp)
+        }
+        None => bail!("Event has no common section"),
+    }
+}
+
+impl SubCommandParserRunner for Merge {
+    fn run(&mut self, _: Modules) -> Result<()> {
+        // Create running instance that will handle signal termination.
+        let run = Running::new();
+        run.register_term_signals()?;
+
+        let inputs: Vec<_> = self.inputs.iter().map(|i| parse_input(i)).collect();
+
+        // Make sure we don't use one of the input files as the output one, as
+        // it would be truncated before being read.
+        if inputs.iter().any(|(path, _)| same_file(path, &self.out)) {
+            bail!("Cannot merge events into one of the input files. Please specify a different output file.");
+        }
+
+        let mut inputs = inputs
+            .into_iter()
+            .map(|(path, label)| MergeInput::new(&path, label))
+            .collect::<Result<Vec<_>>>()?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.out)
+            .or_else(|_| bail!("Could not create or open '{}'", self.out.display()))?;
+        let mut output = PrintEvent::new(
+            Box::new(BufWriter::new(
+                FileCompression::from(self.compression).writer(file)?,
+            )),
+            FileFormat::from(self.format).into(),
+        );
+
+        // Timestamps of the merged file are wall-clock ones, no offset is
+        // needed to convert them.
+        let mut startup = Event::new();
+        startup.insert_section(SectionId::Common, Box::<CommonEvent>::default())?;
+        startup.insert_section(
+            SectionId::Startup,
+            Box::new(StartupEvent {
+                retis_version: option_env!("RELEASE_VERSION")
+                    .unwrap_or("unspec")
+                    .to_string(),
+                clock_monotonic_offset: TimeSpec::new(0, 0),
+                sampling: None,
+            }),
+        )?;
+        output.process_one(&startup)?;
+
+        // Merge the inputs, always writing the oldest pending event first.
+        // Each input is expected to be (mostly) time-ordered already.
+        let mut pending = Vec::with_capacity(inputs.len());
+        let mut heap = BinaryHeap::new();
+        for (idx, input) in inputs.iter_mut().enumerate() {
+            let next = input.next_event()?;
+            if let Some((timestamp, _)) = &next {
+                heap.push(Reverse((*timestamp, idx)));
+            }
+            pending.push(next.map(|(_, event)| event));
+        }
+
+        while run.running() {
+            let idx = match heap.pop() {
+                Some(Reverse((_, idx))) => idx,
+                None => break,
+            };
+
+            if let Some(event) = pending[idx].take() {
+                output.process_one(&event)?;
+            }
+
+            if let Some((timestamp, event)) = inputs[idx].next_event()? {
+                heap.push(Reverse((timestamp, idx)));
+                pending[idx] = Some(event);
+            }
+        }
+
+        output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tests::TestEvent;
+
+    #[test]
+    fn input() {
+        assert_eq!(
+            parse_input("/tmp/retis.data"),
+            (PathBuf::from("/tmp/retis.data"), "retis.data".to_string())
+        );
+        assert_eq!(
+            parse_input("/tmp/retis.data:host1"),
+            (PathBuf::from("/tmp/retis.data"), "host1".to_string())
+        );
+    }
+
+    #[test]
+    fn wall_clock() -> Result<()> {
+        let mut event = TestEvent::new()
+            .common(3_000_000_000)
+            .section(
+                SectionId::Lost,
+                LostEvent {
+                    probe: "kfree_skb_reason".to_string(),
+                    count: 42,
+                    window_start: 2_000_000_000,
+                    window_end: 3_000_000_000,
+                },
+            )
+            .build();
+
+        assert_eq!(to_wall_clock(&mut event, 1_000_000_000)?, 4_000_000_000);
+
+        let lost = event.get_section::<LostEvent>(SectionId::Lost).unwrap();
+        assert_eq!(lost.window_start, 3_000_000_000);
+        assert_eq!(lost.window_end, 4_000_000_000);
+        Ok(())
+    }
+}
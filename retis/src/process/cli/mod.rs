@@ -2,6 +2,24 @@
 //!
 //! Provides cli commands to perform some post-processing.
 
+pub(crate) mod convert;
+pub(crate) use convert::*;
+
+pub(crate) mod export;
+pub(crate) use export::*;
+
+pub(crate) mod filter;
+pub(crate) use filter::*;
+
+pub(crate) mod graph;
+pub(crate) use graph::*;
+
+pub(crate) mod latency;
+pub(crate) use latency::*;
+
+pub(crate) mod merge;
+pub(crate) use merge::*;
+
 pub(crate) mod pcap;
 pub(crate) use self::pcap::*;
 
@@ -13,5 +31,11 @@ pub(crate) mod python;
 #[cfg(feature = "python")]
 pub(crate) use python::*;
 
+pub(crate) mod receive;
+pub(crate) use receive::*;
+
 pub(crate) mod sort;
 pub(crate) use sort::*;
+
+pub(crate) mod stats;
+pub(crate) use stats::*;
@@ -1,14 +1,14 @@
 use std::{
     borrow::Cow,
-    collections::HashMap,
-    fs::OpenOptions,
-    io::Write,
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
     path::{Path, PathBuf},
     time::Duration,
 };
 
 use anyhow::{anyhow, bail, Result};
-use clap::{arg, Parser};
+use clap::{arg, Parser, ValueEnum};
 use log::{info, warn};
 use pcap_file::{
     pcapng::{
@@ -21,12 +21,16 @@ use pcap_file::{
     DataLink,
 };
 
+use super::{sort::SortBuffer, stats::StatsKey};
 use crate::{
     cli::*,
     core::{kernel::Symbol, probe::kernel::utils::*},
     events::{file::FileEventsFactory, CommonEvent, KernelEvent, SkbEvent, *},
     helpers::signals::Running,
     module::Modules,
+    process::{
+        display::EventOutput, filter::EventFilter, series::for_each_series, tracking::AddTracking,
+    },
 };
 
 /// Statistics of the event parser about events (processed, skipped, etc).
@@ -43,14 +47,55 @@ struct EventParserStats {
     missing_dev: u32,
     /// Events w/o a netns section (fake one was used instead).
     missing_ns: u32,
+    /// Events lost at collection time, as reported by lost events records.
+    lost: u64,
 }
 
-/// Events parser: handles the logic to convert our events to the PCAP format
-/// that is represented by the internal writer.
-struct EventParser<'a, W: Write> {
-    writer: &'a mut PcapNgWriter<W>,
+/// Keys used to split packets between PCAP-ng files.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum SplitBy {
+    /// One file per packet series (tracking id).
+    Series,
+    /// One file per network namespace.
+    Netns,
+    /// One file per network interface (netns and ifindex).
+    Ifindex,
+}
+
+/// A PCAP-ng output, along with the network interfaces described in it.
+struct PcapNgOutput<W: Write> {
+    writer: PcapNgWriter<W>,
     /// Known network interfaces and their PCAP id: netns|ifindex -> pcap id.
     ifaces: HashMap<u64, u32>,
+}
+
+impl<W: Write> PcapNgOutput<W> {
+    fn new(writer: PcapNgWriter<W>) -> Self {
+        Self {
+            writer,
+            ifaces: HashMap::new(),
+        }
+    }
+}
+
+/// Opens the PCAP-ng output of a split key. The second argument tells if the
+/// output was opened before, in which case it must be appended to (starting a
+/// new PCAP-ng section).
+type SplitOpener<W> = Box<dyn FnMut(&str, bool) -> Result<PcapNgWriter<W>>>;
+
+/// Split key of the packets without tracking information.
+const UNTRACKED_KEY: &str = "untracked";
+
+/// Events parser: handles the logic to convert our events to the PCAP format
+/// that is represented by the internal writer(s).
+struct EventParser<W: Write> {
+    /// Outputs, by split key. When packets are not split, a single output is
+    /// used with an empty key.
+    outputs: HashMap<String, PcapNgOutput<W>>,
+    /// How packets are split between outputs, if they are.
+    split: Option<(SplitBy, SplitOpener<W>)>,
+    /// Split keys whose output was opened, including closed ones.
+    opened: HashSet<String>,
     /// Statistics.
     stats: EventParserStats,
 }
@@ -68,12 +113,24 @@ macro_rules! some_or_return {
     };
 }
 
-impl<'a, W: Write> EventParser<'a, W> {
+impl<W: Write> EventParser<W> {
     /// Creates a new EventParser from a PcapNgWriter<W: Write>.
-    fn from(writer: &'a mut PcapNgWriter<W>) -> Self {
+    fn from(writer: PcapNgWriter<W>) -> Self {
         Self {
-            writer,
-            ifaces: HashMap::new(),
+            outputs: HashMap::from([(String::new(), PcapNgOutput::new(writer))]),
+            split: None,
+            opened: HashSet::new(),
+            stats: EventParserStats::default(),
+        }
+    }
+
+    /// Creates a new EventParser splitting packets between multiple outputs,
+    /// opened on demand for each key.
+    fn split(by: SplitBy, opener: SplitOpener<W>) -> Self {
+        Self {
+            outputs: HashMap::new(),
+            split: Some((by, opener)),
+            opened: HashSet::new(),
             stats: EventParserStats::default(),
         }
     }
@@ -117,13 +174,35 @@ impl<'a, W: Write> EventParser<'a, W> {
             }
         };
 
+        // Find the output of the packet, opening it if needed.
+        let split_key = match &self.split {
+            None => String::new(),
+            Some((SplitBy::Series, _)) => event
+                .get_section::<TrackingInfo>(SectionId::Tracking)
+                .map(|t| format!("{:x}", t.skb.tracking_id()))
+                .unwrap_or_else(|| UNTRACKED_KEY.to_string()),
+            Some((SplitBy::Netns, _)) => netns.to_string(),
+            Some((SplitBy::Ifindex, _)) => format!("{netns}-{ifindex}"),
+        };
+        let output = match self.outputs.entry(split_key) {
+            Entry::Occupied(output) => output.into_mut(),
+            Entry::Vacant(entry) => {
+                let reopen = !self.opened.insert(entry.key().clone());
+                let writer = match &mut self.split {
+                    Some((_, opener)) => opener(entry.key(), reopen)?,
+                    None => bail!("No output to write packets to"),
+                };
+                entry.insert(PcapNgOutput::new(writer))
+            }
+        };
+
         // If we see this iface for the first time, add a description block.
         let key: u64 = (netns as u64) << 32 | ifindex as u64;
-        let id = match self.ifaces.contains_key(&key) {
+        let id = match output.ifaces.contains_key(&key) {
             // Unwrap if contains is true.
-            true => *self.ifaces.get(&key).unwrap(),
+            true => *output.ifaces.get(&key).unwrap(),
             false => {
-                self.writer.write_block(
+                output.writer.write_block(
                     &InterfaceDescriptionBlock {
                         linktype: DataLink::ETHERNET,
                         snaplen: 0xffff,
@@ -141,23 +220,31 @@ impl<'a, W: Write> EventParser<'a, W> {
                     .into_block(),
                 )?;
 
-                let id = self.ifaces.len() as u32;
-                self.ifaces.insert(key, id);
+                let id = output.ifaces.len() as u32;
+                output.ifaces.insert(key, id);
                 id
             }
         };
 
+        // Annotate the packet with information from the event, so the kernel
+        // journey of packets can be followed in the capture.
+        let mut options: Vec<EnhancedPacketOption> = annotations(event, kernel)
+            .into_iter()
+            .map(|a| EnhancedPacketOption::Comment(Cow::Owned(a)))
+            .collect();
+        let flags = direction(&kernel.symbol, skb.dev.as_ref());
+        if flags != DIRECTION_UNKNOWN {
+            options.push(EnhancedPacketOption::Flags(flags));
+        }
+
         // Add the packet itself.
-        self.writer.write_block(
+        output.writer.write_block(
             &EnhancedPacketBlock {
                 interface_id: id,
                 timestamp: Duration::from_nanos(common.timestamp),
                 original_len: packet.len,
                 data: Cow::Borrowed(&packet.packet.0),
-                options: vec![EnhancedPacketOption::Comment(Cow::Owned(format!(
-                    "probe={}:{}",
-                    &kernel.probe_type, &kernel.symbol
-                )))],
+                options,
             }
             .into_block(),
         )?;
@@ -165,10 +252,45 @@ impl<'a, W: Write> EventParser<'a, W> {
         Ok(())
     }
 
+    /// Account for events lost at collection time, if the event is a lost
+    /// events record.
+    fn account_lost(&mut self, event: &Event) {
+        if let Some(lost) = event.get_section::<LostEvent>(SectionId::Lost) {
+            self.stats.lost = self.stats.lost.saturating_add(lost.count);
+        }
+    }
+
+    /// Flush the underlying writer.
+    fn flush(&mut self) -> Result<()> {
+        self.outputs
+            .values_mut()
+            .try_for_each(|output| Ok(output.writer.get_mut().flush()?))
+    }
+
+    /// Close the outputs of the series processed so far, when splitting
+    /// packets by series. The output of untracked packets is kept open, as it
+    /// is shared by all of them.
+    fn close_series(&mut self) -> Result<()> {
+        if !matches!(self.split, Some((SplitBy::Series, _))) {
+            return Ok(());
+        }
+
+        let untracked = self.outputs.remove(UNTRACKED_KEY);
+        self.flush()?;
+        self.outputs.clear();
+        if let Some(untracked) = untracked {
+            self.outputs.insert(UNTRACKED_KEY.to_string(), untracked);
+        }
+        Ok(())
+    }
+
     /// Report parser statistics. Should be called after processing was
     /// completed.
     fn report_stats(&self) {
         info!("{} event(s) were processed", self.stats.processed);
+        if self.split.is_some() {
+            info!("{} file(s) were written", self.opened.len());
+        }
 
         if self.stats.missing_skb != 0 {
             warn!(
@@ -194,6 +316,160 @@ impl<'a, W: Write> EventParser<'a, W> {
                 self.stats.missing_ns
             );
         }
+        if self.stats.lost != 0 {
+            warn!(
+                "{} event(s) were lost during the collection, some packets might be missing",
+                self.stats.lost
+            );
+        }
+    }
+}
+
+/// Direction of a packet, as stored in the `epb_flags` option (bits 0-1).
+const DIRECTION_UNKNOWN: u32 = 0;
+const DIRECTION_INBOUND: u32 = 1;
+const DIRECTION_OUTBOUND: u32 = 2;
+
+/// Probes known to be on the receive path.
+const RX_PROBES: &[&str] = &[
+    "net:netif_receive_skb",
+    "net:netif_rx",
+    "net:napi_gro_receive_entry",
+    "net:napi_gro_frags_entry",
+    "__netif_receive_skb_core",
+    "netif_receive_skb",
+    "ip_rcv",
+    "ipv6_rcv",
+];
+
+/// Probes known to be on the transmit path.
+const TX_PROBES: &[&str] = &[
+    "net:net_dev_queue",
+    "net:net_dev_start_xmit",
+    "net:net_dev_xmit",
+    "__dev_queue_xmit",
+    "dev_queue_xmit",
+    "dev_hard_start_xmit",
+];
+
+/// Guess the direction of a packet, relative to the device it was seen on.
+/// Well known probes are used first, then the device the packet was received
+/// on (if any).
+fn direction(symbol: &str, dev: Option<&SkbDevEvent>) -> u32 {
+    if RX_PROBES.contains(&symbol) {
+        return DIRECTION_INBOUND;
+    }
+    if TX_PROBES.contains(&symbol) {
+        return DIRECTION_OUTBOUND;
+    }
+
+    match dev {
+        Some(SkbDevEvent {
+            ifindex,
+            rx_ifindex: Some(rx_ifindex),
+            ..
+        }) if *ifindex != 0 => match rx_ifindex == ifindex {
+            true => DIRECTION_INBOUND,
+            false => DIRECTION_OUTBOUND,
+        },
+        _ => DIRECTION_UNKNOWN,
+    }
+}
+
+/// Get the annotations of a packet from its event: probe, tracking id, drop
+/// reason, nft verdict and conntrack state (when available).
+fn annotations(event: &Event, kernel: &KernelEvent) -> Vec<String> {
+    let mut annotations = vec![format!("probe={}:{}", kernel.probe_type, kernel.symbol)];
+
+    let tracking = event
+        .get_section::<TrackingInfo>(SectionId::Tracking)
+        .map(|t| &t.skb)
+        .or_else(|| event.get_section::<SkbTrackingEvent>(SectionId::SkbTracking));
+    if let Some(tracking) = tracking {
+        annotations.push(format!("tracking_id={:x}", tracking.tracking_id()));
+    }
+
+    if let Some(reason) = StatsKey::DropReason.value(event) {
+        annotations.push(format!("drop_reason={reason}"));
+    }
+
+    if let Some(nft) = event.get_section::<NftEvent>(SectionId::Nft) {
+        annotations.push(format!(
+            "nft_verdict={} ({}/{})",
+            nft.verdict, nft.table_name, nft.chain_name
+        ));
+    }
+
+    if let Some(state) = StatsKey::CtState.value(event) {
+        annotations.push(format!("ct_state={state}"));
+    }
+
+    annotations
+}
+
+/// Filter matching events based on the probe they were reported from.
+struct ProbeFilter(Vec<(String, String)>);
+
+impl ProbeFilter {
+    /// Creates a new ProbeFilter from a list of probes following the
+    /// [TYPE:]TARGET pattern. An empty list matches all probes.
+    fn new(probes: &[String]) -> Result<Self> {
+        let probes = probes
+            .iter()
+            .map(|probe| {
+                let (probe_type, target) = parse_cli_probe(probe)?;
+                let symbol = Symbol::from_name_no_inspect(target);
+                Ok((probe_type.to_str().to_string(), symbol.name()))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self(probes))
+    }
+
+    /// Does a probe match the filter?
+    fn matches(&self, r#type: &str, name: &str) -> bool {
+        self.0.is_empty() || self.0.iter().any(|(t, n)| t == r#type && n == name)
+    }
+}
+
+/// Writes events to a PCAP-ng output as they are collected. Packets are
+/// flushed right away so the output can be consumed while it grows.
+pub(crate) struct PcapOutput<W: Write> {
+    parser: EventParser<W>,
+    filter: ProbeFilter,
+}
+
+impl<W: Write> PcapOutput<W> {
+    /// Creates a new PcapOutput, only writing packets from the given probes (or
+    /// from all probes if the list is empty).
+    pub(crate) fn new(writer: W, probes: &[String]) -> Result<Self> {
+        Ok(Self {
+            parser: EventParser::from(PcapNgWriter::new(writer)?),
+            filter: ProbeFilter::new(probes)?,
+        })
+    }
+}
+
+impl<W: Write> EventOutput for PcapOutput<W> {
+    fn process_one(&mut self, e: &Event) -> Result<()> {
+        self.parser.account_lost(e);
+        if let Some(kernel) = e.get_section::<KernelEvent>(SectionId::Kernel) {
+            if self.filter.matches(&kernel.probe_type, &kernel.symbol) {
+                self.parser.parse(e)?;
+                self.parser.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.parser.flush()
+    }
+}
+
+impl<W: Write> Drop for PcapOutput<W> {
+    fn drop(&mut self) {
+        self.parser.report_stats();
     }
 }
 
@@ -205,9 +481,10 @@ pub(crate) struct Pcap {
         short,
         long,
         help = "Filter events from this probe. Probes should follow the [TYPE:]TARGET pattern.
+Can be used multiple times. If not set, packets from all probes are exported.
 See `retis collect --help` for more details on the probe format."
     )]
-    pub(super) probe: String,
+    pub(super) probe: Vec<String>,
     #[arg(
         short,
         long,
@@ -216,47 +493,119 @@ See `retis collect --help` for more details on the probe format."
     pub(super) out: Option<PathBuf>,
     #[arg(default_value = "retis.data", help = "File from which to read events")]
     pub(super) input: PathBuf,
+    #[arg(
+        long,
+        help = "Only export packets from events matching a filter expression.
+
+Example: --filter \"skb.ip.daddr == 10.0.0.1\""
+    )]
+    pub(super) filter: Option<EventFilter>,
+    #[arg(
+        long,
+        value_enum,
+        requires = "out",
+        help = "Split packets into one file per key. Files are named after the output file and the
+key (e.g. retis_<key>.pcap for --out retis.pcap). Series are identified by their tracking id,
+events being sorted in series first (see --max-buffer)."
+    )]
+    pub(super) split_by: Option<SplitBy>,
+    #[arg(
+        long,
+        value_parser = parse_tracking_id,
+        help = "Only export packets from a single series, identified by its tracking id (as
+reported by `retis sort`)."
+    )]
+    pub(super) series: Option<u128>,
+    #[command(flatten)]
+    pub(super) buffer: SortBuffer,
+}
+
+/// Parse a tracking id, in hexadecimal (e.g. "#28c6e7a0fe1ff" or "28c6e7a0fe1ff").
+fn parse_tracking_id(id: &str) -> Result<u128> {
+    let hex = id.trim_start_matches('#').trim_start_matches("0x");
+    u128::from_str_radix(hex, 16).or_else(|_| bail!("Invalid tracking id '{id}'"))
+}
+
+/// Get the path of the output file of a split key, by appending the key to the
+/// file name of the base output (before its extension).
+fn split_path(base: &Path, key: &str) -> PathBuf {
+    let stem = base
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    let name = match base.extension() {
+        Some(ext) => format!("{stem}_{key}.{}", ext.to_string_lossy()),
+        None => format!("{stem}_{key}"),
+    };
+    base.with_file_name(name)
+}
+
+/// Create (or truncate) an output file, or open it for appending.
+fn open_file(path: &Path, append: bool) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .or_else(|_| bail!("Could not create or open '{}'", path.display()))
 }
 
 impl SubCommandParserRunner for Pcap {
     fn run(&mut self, _: Modules) -> Result<()> {
-        let (probe_type, target) = parse_cli_probe(&self.probe)?;
-        let symbol = Symbol::from_name_no_inspect(target);
-
-        // Create a PCAP writer to push our events / metadata.
-        let mut writer = PcapNgWriter::new(match &self.out {
-            Some(file) => OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(file)
-                .or_else(|_| bail!("Could not create or open '{}'", file.display()))?,
-            None => OpenOptions::new()
-                .write(true)
-                .open("/proc/self/fd/1")
-                .or_else(|_| bail!("Could not open stdout"))?,
-        })?;
-
-        // Filtering logic.
-        let filter = |r#type: &str, name: &str| -> bool {
-            if name == symbol.name() && r#type == probe_type.to_str() {
-                return true;
+        let filter = ProbeFilter::new(&self.probe)?;
+
+        // Create the PCAP writer(s) to push our events / metadata.
+        let mut parser = match (self.split_by, &self.out) {
+            (Some(by), Some(out)) => {
+                let out = out.clone();
+                EventParser::split(
+                    by,
+                    Box::new(move |key, append| {
+                        let file = open_file(&split_path(&out, key), append)?;
+                        Ok(PcapNgWriter::new(BufWriter::new(file))?)
+                    }),
+                )
             }
-            false
+            (_, Some(file)) => {
+                EventParser::from(PcapNgWriter::new(BufWriter::new(open_file(file, false)?))?)
+            }
+            (_, None) => EventParser::from(PcapNgWriter::new(BufWriter::new(
+                OpenOptions::new()
+                    .write(true)
+                    .open("/proc/self/fd/1")
+                    .or_else(|_| bail!("Could not open stdout"))?,
+            ))?),
+        };
+
+        // Events are sorted in series when splitting by series, so each
+        // series output can be closed once the series was processed.
+        let sort = match self.split_by {
+            Some(SplitBy::Series) => Some(self.buffer.max_buffer),
+            _ => None,
         };
 
         handle_events(
             self.input.as_path(),
-            &filter,
-            &mut EventParser::from(&mut writer),
+            &|r#type: &str, name: &str| filter.matches(r#type, name),
+            self.filter.as_ref(),
+            self.series,
+            sort,
+            &mut parser,
         )
     }
 }
 
-/// Internal logic to retrieve our events to feed the parser.
+/// Internal logic to retrieve our events to feed the parser. When `sort` is
+/// set, events are sorted in series (buffering at most `sort` events, see
+/// `for_each_series`) and the series outputs of the parser are closed once
+/// each series was processed.
 fn handle_events<W>(
     input: &Path,
     filter: &dyn Fn(&str, &str) -> bool,
+    event_filter: Option<&EventFilter>,
+    series: Option<u128>,
+    sort: Option<usize>,
     parser: &mut EventParser<W>,
 ) -> Result<()>
 where
@@ -271,27 +620,206 @@ where
 
     // See if we matched (not processed!) at least one event.
     let mut matched = false;
-    while run.running() {
-        match factory.next_event()? {
-            Some(event) => {
-                if let Some(kernel) = event.get_section::<KernelEvent>(SectionId::Kernel) {
-                    // Check the event is matching the requested symbol.
-                    if !filter(&kernel.probe_type, &kernel.symbol) {
-                        continue;
-                    }
-                    matched = true;
+    let mut process = |event: &Event, parser: &mut EventParser<W>| -> Result<()> {
+        parser.account_lost(event);
+        if let Some(kernel) = event.get_section::<KernelEvent>(SectionId::Kernel) {
+            // Check the event is matching the requested symbol.
+            if !filter(&kernel.probe_type, &kernel.symbol) {
+                return Ok(());
+            }
+            matched = true;
+
+            if event_filter.is_some_and(|f| !f.matches(event)) {
+                return Ok(());
+            }
+
+            if let Some(id) = series {
+                if event
+                    .get_section::<TrackingInfo>(SectionId::Tracking)
+                    .map(|t| t.skb.tracking_id())
+                    != Some(id)
+                {
+                    return Ok(());
+                }
+            }
+
+            parser.parse(event)?;
+        }
+        Ok(())
+    };
 
-                    parser.parse(&event)?;
+    match sort {
+        Some(max_buffer) => for_each_series(&mut factory, max_buffer, &run, |s| {
+            s.events.iter().try_for_each(|e| process(e, parser))?;
+            parser.close_series()
+        })?,
+        None => {
+            // Series are only known once tracking information is added.
+            let mut tracker = series.map(|_| AddTracking::new());
+            while run.running() {
+                match factory.next_event()? {
+                    Some(mut event) => {
+                        if let Some(tracker) = tracker.as_mut() {
+                            if event
+                                .get_section::<TrackingInfo>(SectionId::Tracking)
+                                .is_none()
+                            {
+                                tracker.process_one(&mut event)?;
+                            }
+                        }
+                        process(&event, parser)?;
+                    }
+                    None => break,
                 }
             }
-            None => break,
         }
     }
 
     if !matched {
-        bail!("Probe not found in the events");
+        bail!("No event from the requested probe(s) found");
     }
 
+    parser.flush()?;
     parser.report_stats();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tests::TestEvent;
+
+    #[test]
+    fn packet_annotations() {
+        let kernel = KernelEvent {
+            symbol: "skb:kfree_skb".to_string(),
+            probe_type: "raw_tracepoint".to_string(),
+            stack_trace: None,
+        };
+
+        let event = TestEvent::new()
+            .section(
+                SectionId::SkbTracking,
+                SkbTrackingEvent {
+                    orig_head: 0x10,
+                    timestamp: 0x20,
+                    skb: 0x10,
+                },
+            )
+            .drop("NO_SOCKET")
+            .build();
+
+        assert_eq!(
+            annotations(&event, &kernel),
+            vec![
+                "probe=raw_tracepoint:skb:kfree_skb",
+                "tracking_id=200000000000000010",
+                "drop_reason=NO_SOCKET",
+            ]
+        );
+    }
+
+    #[test]
+    fn packet_direction() {
+        let dev = |rx_ifindex| SkbDevEvent {
+            name: "eth0".to_string(),
+            ifindex: 2,
+            rx_ifindex,
+        };
+
+        assert_eq!(direction("net:netif_receive_skb", None), DIRECTION_INBOUND);
+        assert_eq!(direction("dev_queue_xmit", None), DIRECTION_OUTBOUND);
+        assert_eq!(
+            direction("ip_forward", Some(&dev(Some(2)))),
+            DIRECTION_INBOUND
+        );
+        assert_eq!(
+            direction("ip_output", Some(&dev(Some(3)))),
+            DIRECTION_OUTBOUND
+        );
+        assert_eq!(direction("ip_output", Some(&dev(None))), DIRECTION_UNKNOWN);
+        assert_eq!(direction("ip_output", None), DIRECTION_UNKNOWN);
+    }
+
+    #[test]
+    fn split_paths() {
+        assert_eq!(
+            split_path(Path::new("/tmp/retis.pcap"), "4026531840"),
+            PathBuf::from("/tmp/retis_4026531840.pcap")
+        );
+        assert_eq!(
+            split_path(Path::new("retis"), "4026531840-2"),
+            PathBuf::from("retis_4026531840-2")
+        );
+    }
+
+    #[test]
+    fn split_series() -> Result<()> {
+        use std::{cell::RefCell, rc::Rc};
+
+        use crate::events::helpers::RawPacket;
+
+        let packet = |id: Option<u64>| -> Event {
+            let event = TestEvent::new()
+                .common(0)
+                .kernel("ip_rcv", "kprobe")
+                .section(
+                    SectionId::Skb,
+                    SkbEvent {
+                        packet: Some(SkbPacketEvent {
+                            len: 4,
+                            capture_len: 4,
+                            packet: RawPacket(vec![0; 4]),
+                        }),
+                        ..Default::default()
+                    },
+                );
+            match id {
+                Some(id) => event.tracking(id, id, 0),
+                None => event,
+            }
+            .build()
+        };
+
+        let opened = Rc::new(RefCell::new(Vec::new()));
+        let opener_opened = opened.clone();
+        let mut parser = EventParser::split(
+            SplitBy::Series,
+            Box::new(move |key, append| {
+                opener_opened.borrow_mut().push((key.to_string(), append));
+                Ok(PcapNgWriter::new(Vec::new())?)
+            }),
+        );
+
+        // Outputs of the series are closed once processed, except the one of
+        // untracked packets. Series seen again are appended to.
+        parser.parse(&packet(Some(1)))?;
+        parser.parse(&packet(None))?;
+        parser.close_series()?;
+        assert_eq!(parser.outputs.len(), 1);
+        parser.parse(&packet(Some(1)))?;
+        parser.parse(&packet(None))?;
+        parser.close_series()?;
+
+        assert_eq!(
+            *opened.borrow(),
+            vec![
+                ("10000000000000001".to_string(), false),
+                ("untracked".to_string(), false),
+                ("10000000000000001".to_string(), true),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tracking_ids() {
+        assert_eq!(
+            parse_tracking_id("#28c6e7a0fe1ff").unwrap(),
+            0x28c6e7a0fe1ff
+        );
+        assert_eq!(parse_tracking_id("0x10").unwrap(), 0x10);
+        assert_eq!(parse_tracking_id("10").unwrap(), 0x10);
+        assert!(parse_tracking_id("foo").is_err());
+    }
+}
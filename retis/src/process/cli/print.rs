@@ -16,7 +16,7 @@ use crate::{
     },
     helpers::signals::Running,
     module::Modules,
-    process::display::*,
+    process::{display::*, filter::EventFilter},
 };
 
 /// Print stored events to stdout
@@ -31,6 +31,12 @@ pub(crate) struct Print {
     pub(super) format: CliDisplayFormat,
     #[arg(long, help = "Print the time as UTC")]
     pub(super) utc: bool,
+    /// Only print events matching a filter expression. When printing series,
+    /// series having at least one matching event are printed.
+    ///
+    /// Example: --filter 'skb-drop.drop_reason == NETFILTER_DROP && kernel.symbol =~ "kfree_*"'
+    #[arg(long)]
+    pub(super) filter: Option<EventFilter>,
 }
 
 impl SubCommandParserRunner for Print {
@@ -59,7 +65,12 @@ impl SubCommandParserRunner for Print {
 
                 while run.running() {
                     match factory.next_event()? {
-                        Some(event) => event_output.process_one(&event)?,
+                        Some(event) => {
+                            if self.filter.as_ref().is_some_and(|f| !f.matches(&event)) {
+                                continue;
+                            }
+                            event_output.process_one(&event)?
+                        }
                         None => break,
                     }
                 }
@@ -71,7 +82,16 @@ impl SubCommandParserRunner for Print {
 
                 while run.running() {
                     match factory.next_series()? {
-                        Some(series) => series_output.process_one(&series)?,
+                        Some(series) => {
+                            if self
+                                .filter
+                                .as_ref()
+                                .is_some_and(|f| !f.matches_series(&series))
+                            {
+                                continue;
+                            }
+                            series_output.process_one(&series)?
+                        }
                         None => break,
                     }
                 }
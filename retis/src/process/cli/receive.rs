@@ -0,0 +1,128 @@
+//! # Receive
+//!
+//! Receive is a command listening on a socket for events streamed by a
+//! collection (see `collect --out-socket`), and printing them or writing them
+//! to a file.
+
+use std::{
+    fs::OpenOptions,
+    io::{stdout, BufWriter},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+use log::info;
+
+use crate::{
+    cli::*,
+    events::{
+        file::{FileEventsFactory, FileType},
+        *,
+    },
+    helpers::{signals::Running, socket::SocketAddr},
+    module::Modules,
+    process::display::*,
+};
+
+/// Receive events streamed by a collection.
+///
+/// Listens on the given address for a single collection streaming its events (see
+/// `collect --out-socket`) and prints them, or writes them to a file. The command
+/// returns once the collection ends.
+#[derive(Parser, Debug, Default)]
+#[command(name = "receive")]
+pub(crate) struct Receive {
+    /// Address to listen on: unix:PATH or tcp:HOST:PORT.
+    // Option is only used to allow deriving Default, the argument is required.
+    #[arg(required = true)]
+    pub(super) address: Option<SocketAddr>,
+
+    /// Write the events to a file rather than to stdout.
+    #[arg(short, long)]
+    pub(super) out: Option<PathBuf>,
+
+    /// Write events to stdout even if --out is used.
+    #[arg(long, default_value = "false")]
+    pub(super) print: bool,
+
+    /// Format used when printing an event.
+    #[arg(long)]
+    #[clap(value_enum, default_value_t=CliDisplayFormat::MultiLine)]
+    pub(super) format: CliDisplayFormat,
+
+    /// Print the time as UTC.
+    #[arg(long)]
+    pub(super) utc: bool,
+}
+
+impl SubCommandParserRunner for Receive {
+    fn run(&mut self, _: Modules) -> Result<()> {
+        // Create running instance that will handle signal termination.
+        let run = Running::new();
+        run.register_term_signals()?;
+
+        let addr = self
+            .address
+            .as_ref()
+            .ok_or_else(|| anyhow!("An address to listen on is required"))?;
+
+        let mut printers = Vec::new();
+
+        if let Some(out) = &self.out {
+            printers.push(PrintEvent::new(
+                Box::new(BufWriter::new(
+                    OpenOptions::new()
+                        .create(true)
+                        .write(true)
+                        .truncate(true)
+                        .open(out)
+                        .or_else(|_| bail!("Could not create or open '{}'", out.display()))?,
+                )),
+                PrintEventFormat::Json,
+            ));
+        }
+
+        if self.out.is_none() || self.print {
+            let format = DisplayFormat::new()
+                .multiline(self.format == CliDisplayFormat::MultiLine)
+                .time_format(if self.utc {
+                    TimeFormat::UtcDate
+                } else {
+                    TimeFormat::MonotonicTimestamp
+                });
+
+            printers.push(PrintEvent::new(
+                Box::new(stdout()),
+                PrintEventFormat::Text(format),
+            ));
+        }
+
+        let listener = addr.listen()?;
+        info!("Waiting for events on {addr}");
+
+        let stream = match listener.accept(&run)? {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+        // Unblock reading the stream on termination.
+        stream.shutdown_on_termination(&run)?;
+
+        let mut factory = FileEventsFactory::from_reader(stream)?;
+        if matches!(factory.file_type(), FileType::Series) {
+            bail!("Receiving event series is not supported");
+        }
+
+        while run.running() {
+            match factory.next_event()? {
+                Some(event) => printers
+                    .iter_mut()
+                    .try_for_each(|p| p.process_one(&event))?,
+                None => break,
+            }
+        }
+
+        printers.iter_mut().try_for_each(|p| p.flush())?;
+        Ok(())
+    }
+}
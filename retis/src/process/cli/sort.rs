@@ -1,26 +1,61 @@
 //! # Sort
 //!
-//! Sort rearranges the events so they are grouped by skb tracking id (or OVS queue_id if present)
+//! Sort rearranges the events so they are grouped by skb tracking id (or OVS queue_id if present),
+//! and optionally by flow.
 
 use std::{
+    env,
     fs::OpenOptions,
     io::{stdout, BufWriter},
     path::PathBuf,
 };
 
 use anyhow::{bail, Result};
-use clap::Parser;
+use clap::{Args, Parser, ValueEnum};
+use log::warn;
 
 use crate::{
     cli::*,
-    events::{file::FileEventsFactory, *},
+    events::{
+        file::{FileCompression, FileEventsFactory, FileFormat},
+        *,
+    },
     helpers::signals::Running,
     module::Modules,
-    process::{display::*, series::EventSorter, tracking::AddTracking},
+    process::{
+        display::*,
+        filter::EventFilter,
+        flow::FlowGrouper,
+        series::{EventSorter, ExternalSorter},
+        tracking::AddTracking,
+    },
 };
 
 /// The default size of the sorting buffer
 const DEFAULT_BUFFER: usize = 1000;
+/// The default number of events kept in memory by the external sorter
+const DEFAULT_SPILL_SIZE: usize = 100000;
+
+/// Sorting buffer of the commands working on series, which sort events on the
+/// fly if needed.
+#[derive(Args, Debug, Default)]
+pub(crate) struct SortBuffer {
+    /// Maximum number of events to buffer while sorting events in series, see
+    /// the sort command. A value of zero means the buffer can grow endlessly.
+    #[arg(long, default_value_t = DEFAULT_BUFFER)]
+    pub(super) max_buffer: usize,
+}
+
+/// How events are grouped in series.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub(crate) enum SortGroupBy {
+    /// One series per packet, using the tracking information.
+    #[default]
+    Packet,
+    /// One series per flow, holding the series of all its packets. Flows are identified by their
+    /// 5-tuple (or conntrack original tuple), both directions being grouped together.
+    Flow,
+}
 
 /// Sort stored events in series based on tracking id.
 ///
@@ -41,13 +76,46 @@ pub(crate) struct Sort {
     /// need to increase the size of the buffer to properly sort all events.
     ///
     /// A value of zero means the buffer can grow endlessly.
-    #[arg(long, default_value_t = DEFAULT_BUFFER)]
+    #[arg(long, default_value_t = DEFAULT_BUFFER, conflicts_with = "external")]
     pub(super) max_buffer: usize,
 
+    /// Fully sort the events using temporary files
+    ///
+    /// Events are buffered in memory and, once --spill-size events are buffered, partially built
+    /// series are written to temporary files which are merged at the end. This allows sorting any
+    /// amount of events with bounded memory, at the cost of disk space.
+    #[arg(long)]
+    pub(super) external: bool,
+
+    /// Number of events to buffer in memory before writing them to a temporary file, when using
+    /// --external.
+    #[arg(long, requires = "external", default_value_t = DEFAULT_SPILL_SIZE)]
+    pub(super) spill_size: usize,
+
+    /// Directory in which temporary files are created, when using --external. Defaults to the
+    /// system temporary directory.
+    #[arg(long, requires = "external")]
+    pub(super) tmp_dir: Option<PathBuf>,
+
+    /// Group events by packet or by flow
+    ///
+    /// When grouping by flow, series are only written once all events were processed and are
+    /// kept in memory until then, which is why this can't be used with --external.
+    #[arg(long, value_enum, default_value_t = SortGroupBy::Packet, conflicts_with = "external")]
+    pub(super) group_by: SortGroupBy,
+
     /// Write event series to a file rather than to stdout.
     #[arg(short, long)]
     pub(super) out: Option<PathBuf>,
 
+    /// Format of the event series written to the output file.
+    #[arg(long, requires = "out", value_enum, default_value_t = CliFileFormat::Json)]
+    pub(super) out_format: CliFileFormat,
+
+    /// Compress the event series written to the output file.
+    #[arg(long, requires = "out", value_enum, default_value_t = CliCompression::None)]
+    pub(super) out_compression: CliCompression,
+
     /// Write events to stdout even if --out is used.
     #[arg(long, default_value = "false")]
     pub(super) print: bool,
@@ -60,6 +128,44 @@ pub(crate) struct Sort {
     /// Print the time as UTC.
     #[arg(long)]
     pub(super) utc: bool,
+
+    /// Only output series having at least one event matching a filter
+    /// expression, so the full journey of matching packets is kept. Series not
+    /// related to packets (startup, lost events and markers) are always kept.
+    ///
+    /// Example: --filter "skb-drop.drop_reason == NETFILTER_DROP"
+    #[arg(long)]
+    pub(super) filter: Option<EventFilter>,
+}
+
+impl Sort {
+    /// Output a series, if it matches the filter (if any).
+    fn output(&self, printers: &mut [PrintSeries], series: &EventSeries) -> Result<()> {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|f| !f.matches_series(series))
+        {
+            return Ok(());
+        }
+        printers.iter_mut().try_for_each(|p| p.process_one(series))
+    }
+
+    /// Output a packet series, or add it to its flow when grouping by flow.
+    fn add(
+        &self,
+        printers: &mut [PrintSeries],
+        flows: &mut Option<FlowGrouper>,
+        series: EventSeries,
+    ) -> Result<()> {
+        match flows {
+            Some(flows) => {
+                flows.add(series);
+                Ok(())
+            }
+            None => self.output(printers, &series),
+        }
+    }
 }
 
 impl SubCommandParserRunner for Sort {
@@ -77,8 +183,20 @@ impl SubCommandParserRunner for Sort {
         }
 
         let mut series = EventSorter::new();
+        let mut external = match self.external {
+            true => Some(ExternalSorter::new(
+                &self.tmp_dir.clone().unwrap_or_else(env::temp_dir),
+                self.spill_size,
+            )?),
+            false => None,
+        };
+        let mut flows = match self.group_by {
+            SortGroupBy::Packet => None,
+            SortGroupBy::Flow => Some(FlowGrouper::new()),
+        };
         let mut tracker = AddTracking::new();
         let mut printers = Vec::new();
+        let mut lost: u64 = 0;
 
         if let Some(out) = &self.out {
             let out = match out.canonicalize() {
@@ -94,16 +212,18 @@ impl SubCommandParserRunner for Sort {
                 bail!("Cannot sort a file in-place. Please specify an output file that's different to the input one.");
             }
 
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&out)
+                .or_else(|_| bail!("Could not create or open '{}'", out.display()))?;
+
             printers.push(PrintSeries::new(
                 Box::new(BufWriter::new(
-                    OpenOptions::new()
-                        .create(true)
-                        .write(true)
-                        .truncate(true)
-                        .open(&out)
-                        .or_else(|_| bail!("Could not create or open '{}'", out.display()))?,
+                    FileCompression::from(self.out_compression).writer(file)?,
                 )),
-                PrintEventFormat::Json,
+                FileFormat::from(self.out_format).into(),
             ));
         }
 
@@ -125,9 +245,19 @@ impl SubCommandParserRunner for Sort {
         while run.running() {
             match factory.next_event()? {
                 Some(mut event) => {
+                    // Account for events lost at collection time.
+                    if let Some(section) = event.get_section::<LostEvent>(SectionId::Lost) {
+                        lost = lost.saturating_add(section.count);
+                    }
+
                     // Add tracking information
                     tracker.process_one(&mut event)?;
 
+                    if let Some(external) = external.as_mut() {
+                        external.add(event)?;
+                        continue;
+                    }
+
                     // Add to sorter
                     series.add(event);
 
@@ -136,9 +266,7 @@ impl SubCommandParserRunner for Sort {
                         while series.len() >= self.max_buffer {
                             // Flush the oldest series
                             match series.pop_oldest()? {
-                                Some(series) => printers
-                                    .iter_mut()
-                                    .try_for_each(|p| p.process_one(&series))?,
+                                Some(series) => self.add(&mut printers, &mut flows, series)?,
                                 None => break,
                             };
                         }
@@ -148,17 +276,29 @@ impl SubCommandParserRunner for Sort {
             }
         }
         // Flush remaining events
+        if let Some(external) = external {
+            external.finish(|series| self.add(&mut printers, &mut flows, series))?;
+        }
         while series.len() > 0 {
             match series.pop_oldest()? {
-                Some(series) => printers
-                    .iter_mut()
-                    .try_for_each(|p| p.process_one(&series))?,
+                Some(series) => self.add(&mut printers, &mut flows, series)?,
                 None => break,
             };
         }
+        if let Some(flows) = flows {
+            flows
+                .into_flows()
+                .try_for_each(|flow| self.output(&mut printers, &flow))?;
+        }
 
         // Flush writers
         printers.iter_mut().try_for_each(|p| p.flush())?;
+
+        if lost != 0 {
+            warn!(
+                "{lost} event(s) were lost during the collection, some series might be incomplete"
+            );
+        }
         Ok(())
     }
 }
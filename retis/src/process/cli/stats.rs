@@ -0,0 +1,387 @@
+//! # Stats
+//!
+//! Stats is a post-processing command aggregating stored events (or series) by
+//! a set of keys (e.g. probe, device, drop reason) and reporting the number of
+//! events for each of their values.
+
+use std::{
+    collections::HashMap,
+    io::{stdout, Write},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Result};
+use clap::{Parser, ValueEnum};
+use serde_json::json;
+
+use crate::{
+    cli::*,
+    events::{
+        file::{FileEventsFactory, FileType},
+        *,
+    },
+    helpers::signals::Running,
+    module::Modules,
+};
+
+/// Key events can be aggregated by.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+pub(crate) enum StatsKey {
+    /// Kernel or USDT symbol of the probe which generated the event.
+    Symbol,
+    /// Net device of the packet, as "name (ifindex)".
+    Dev,
+    /// Network namespace of the packet.
+    Netns,
+    /// Drop reason of the packet.
+    DropReason,
+    /// Nftables table and chain.
+    NftChain,
+    /// Nftables verdict.
+    NftVerdict,
+    /// Conntrack state of the packet.
+    CtState,
+}
+
+impl StatsKey {
+    fn name(&self) -> &'static str {
+        match self {
+            StatsKey::Symbol => "symbol",
+            StatsKey::Dev => "dev",
+            StatsKey::Netns => "netns",
+            StatsKey::DropReason => "drop_reason",
+            StatsKey::NftChain => "nft_chain",
+            StatsKey::NftVerdict => "nft_verdict",
+            StatsKey::CtState => "ct_state",
+        }
+    }
+
+    /// Get the value of the key for a given event, if any.
+    pub(super) fn value(&self, event: &Event) -> Option<String> {
+        let skb = || event.get_section::<SkbEvent>(SectionId::Skb);
+        let nft = || event.get_section::<NftEvent>(SectionId::Nft);
+
+        match self {
+            StatsKey::Symbol => event
+                .get_section::<KernelEvent>(SectionId::Kernel)
+                .map(|k| k.symbol.clone())
+                .or_else(|| {
+                    event
+                        .get_section::<UserEvent>(SectionId::Userspace)
+                        .map(|u| u.symbol.clone())
+                }),
+            StatsKey::Dev => skb()?
+                .dev
+                .as_ref()
+                .map(|dev| format!("{} ({})", dev.name, dev.ifindex)),
+            StatsKey::Netns => skb()?.ns.as_ref().map(|ns| ns.netns.to_string()),
+            StatsKey::DropReason => {
+                event
+                    .get_section::<SkbDropEvent>(SectionId::SkbDrop)
+                    .map(|drop| match &drop.subsys {
+                        Some(subsys) => format!("{subsys}/{}", drop.drop_reason),
+                        None => drop.drop_reason.clone(),
+                    })
+            }
+            StatsKey::NftChain => nft().map(|nft| format!("{}/{}", nft.table_name, nft.chain_name)),
+            StatsKey::NftVerdict => nft().map(|nft| nft.verdict.clone()),
+            StatsKey::CtState => event
+                .get_section::<CtEvent>(SectionId::Ct)
+                .map(|ct| ct.state.name().to_string()),
+        }
+    }
+}
+
+/// Parse a comma separated list of keys.
+fn parse_keys(s: &str) -> Result<Vec<StatsKey>> {
+    s.split(',')
+        .map(|key| match StatsKey::from_str(key.trim(), true) {
+            Ok(key) => Ok(key),
+            Err(_) => bail!("Invalid stats key '{key}'"),
+        })
+        .collect()
+}
+
+/// Number of events per value of a set of keys.
+struct StatsTable {
+    keys: Vec<StatsKey>,
+    counts: HashMap<Vec<String>, u64>,
+}
+
+impl StatsTable {
+    /// Get the counts, highest first.
+    fn sorted(&self) -> Vec<(&Vec<String>, u64)> {
+        let mut counts: Vec<_> = self.counts.iter().map(|(k, v)| (k, *v)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        counts
+    }
+}
+
+/// Aggregates events in a set of tables.
+pub(crate) struct EventStats {
+    tables: Vec<StatsTable>,
+    /// Total number of events processed.
+    events: u64,
+    /// Sampling configuration of the collection, if events were sampled.
+    sampling: Option<SamplingConfig>,
+}
+
+impl EventStats {
+    pub(crate) fn new(tables: Vec<Vec<StatsKey>>) -> Self {
+        Self {
+            tables: tables
+                .into_iter()
+                .map(|keys| StatsTable {
+                    keys,
+                    counts: HashMap::new(),
+                })
+                .collect(),
+            events: 0,
+            sampling: None,
+        }
+    }
+
+    /// Account for an event. Events are only accounted in tables for which
+    /// all the keys are found in the event.
+    pub(crate) fn process_one(&mut self, event: &Event) {
+        if let Some(startup) = event.get_section::<StartupEvent>(SectionId::Startup) {
+            self.sampling = startup.sampling.clone();
+            return;
+        }
+        // Lost events and markers are not tied to a probe hit.
+        if event
+            .sections()
+            .any(|s| s == SectionId::Lost || s == SectionId::Marker)
+        {
+            return;
+        }
+
+        self.events += 1;
+        for table in self.tables.iter_mut() {
+            let values: Option<Vec<String>> = table.keys.iter().map(|k| k.value(event)).collect();
+            if let Some(values) = values {
+                *table.counts.entry(values).or_default() += 1;
+            }
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let tables: Vec<_> = self
+            .tables
+            .iter()
+            .map(|table| {
+                let counts: Vec<_> = table
+                    .sorted()
+                    .iter()
+                    .map(|(values, count)| {
+                        let mut entry = serde_json::Map::new();
+                        table.keys.iter().zip(values.iter()).for_each(|(k, v)| {
+                            entry.insert(k.name().to_string(), json!(v));
+                        });
+                        entry.insert("count".to_string(), json!(count));
+                        serde_json::Value::Object(entry)
+                    })
+                    .collect();
+
+                json!({
+                    "keys": table.keys.iter().map(|k| k.name()).collect::<Vec<_>>(),
+                    "counts": counts,
+                })
+            })
+            .collect();
+
+        json!({
+            "events": self.events,
+            "sampling": self.sampling,
+            "tables": tables,
+        })
+    }
+
+    pub(crate) fn write_text<W: Write>(&self, w: &mut W) -> Result<()> {
+        writeln!(w, "{} event(s)", self.events)?;
+        if let Some(sampling) = &self.sampling {
+            if let Some(rate) = sampling.sample_rate {
+                writeln!(w, "Events were sampled (1/{rate})")?;
+            }
+            if let Some(limit) = sampling.rate_limit {
+                writeln!(w, "Events were rate limited ({limit}/s)")?;
+            }
+        }
+
+        for table in self.tables.iter() {
+            let counts = table.sorted();
+
+            // Compute the width of each column, the last one being the count.
+            let mut widths: Vec<usize> = table.keys.iter().map(|k| k.name().len()).collect();
+            widths.push("count".len());
+            for (values, count) in counts.iter() {
+                values
+                    .iter()
+                    .chain([count.to_string()].iter())
+                    .enumerate()
+                    .for_each(|(i, v)| widths[i] = widths[i].max(v.len()));
+            }
+
+            writeln!(w)?;
+            let header: Vec<String> = table
+                .keys
+                .iter()
+                .map(|k| k.name().to_string())
+                .chain(["count".to_string()])
+                .collect();
+            write_row(w, &header, &widths)?;
+            for (values, count) in counts.iter() {
+                let mut row = (*values).clone();
+                row.push(count.to_string());
+                write_row(w, &row, &widths)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a table row: the keys are left-aligned, the count right-aligned.
+fn write_row<W: Write>(w: &mut W, row: &[String], widths: &[usize]) -> Result<()> {
+    let last = row.len() - 1;
+    let line: Vec<String> = row
+        .iter()
+        .enumerate()
+        .map(|(i, v)| match i {
+            i if i == last => format!("{v:>width$}", width = widths[i]),
+            i => format!("{v:<width$}", width = widths[i]),
+        })
+        .collect();
+    writeln!(w, "{}", line.join("  "))?;
+    Ok(())
+}
+
+/// Output format of the stats command.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub(crate) enum StatsFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Aggregate stored events and report the number of events per key.
+///
+/// Reads events (or series) from the INPUT file and counts them by the given
+/// keys. Each use of --by generates a table; multiple keys can be combined in
+/// a single table using a comma separated list.
+#[derive(Parser, Debug, Default)]
+#[command(name = "stats")]
+pub(crate) struct Stats {
+    /// File from which to read events.
+    #[arg(default_value = "retis.data")]
+    pub(super) input: PathBuf,
+
+    /// Keys to aggregate the events by, as a comma separated list (e.g.
+    /// "symbol,drop-reason"). Can be used multiple times. Supported keys:
+    /// symbol, dev, netns, drop-reason, nft-chain, nft-verdict, ct-state.
+    /// Defaults to one table per key.
+    #[arg(long, value_parser = parse_keys)]
+    pub(super) by: Vec<Vec<StatsKey>>,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+    pub(super) format: StatsFormat,
+}
+
+impl SubCommandParserRunner for Stats {
+    fn run(&mut self, _: Modules) -> Result<()> {
+        // Create running instance that will handle signal termination.
+        let run = Running::new();
+        run.register_term_signals()?;
+
+        // Create event factory.
+        let mut factory = FileEventsFactory::new(self.input.as_path())?;
+
+        let tables = match self.by.is_empty() {
+            true => StatsKey::value_variants()
+                .iter()
+                .map(|k| vec![*k])
+                .collect(),
+            false => self.by.clone(),
+        };
+        let mut stats = EventStats::new(tables);
+
+        match factory.file_type() {
+            FileType::Event => {
+                while run.running() {
+                    match factory.next_event()? {
+                        Some(event) => stats.process_one(&event),
+                        None => break,
+                    }
+                }
+            }
+            FileType::Series => {
+                while run.running() {
+                    match factory.next_series()? {
+                        Some(series) => series.events.iter().for_each(|e| stats.process_one(e)),
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        match self.format {
+            StatsFormat::Text => stats.write_text(&mut stdout())?,
+            StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats.to_json())?),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tests::TestEvent;
+
+    fn event(symbol: &str, reason: Option<&str>) -> Event {
+        let event = TestEvent::new().common(0).kernel(symbol, "raw_tracepoint");
+        match reason {
+            Some(reason) => event.drop(reason),
+            None => event,
+        }
+        .build()
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            parse_keys("symbol,drop-reason").unwrap(),
+            vec![StatsKey::Symbol, StatsKey::DropReason]
+        );
+        assert!(parse_keys("symbol,foo").is_err());
+    }
+
+    #[test]
+    fn stats() -> Result<()> {
+        let mut stats = EventStats::new(vec![
+            vec![StatsKey::Symbol],
+            vec![StatsKey::Symbol, StatsKey::DropReason],
+        ]);
+
+        stats.process_one(&event("skb:kfree_skb", Some("NO_SOCKET")));
+        stats.process_one(&event("skb:kfree_skb", Some("NO_SOCKET")));
+        stats.process_one(&event("skb:kfree_skb", Some("NETFILTER_DROP")));
+        stats.process_one(&event("tcp_v4_rcv", None));
+
+        let json = stats.to_json();
+        assert_eq!(json["events"], 4);
+        assert_eq!(json["tables"][0]["counts"][0]["symbol"], "skb:kfree_skb");
+        assert_eq!(json["tables"][0]["counts"][0]["count"], 3);
+        assert_eq!(json["tables"][0]["counts"][1]["count"], 1);
+        assert_eq!(json["tables"][1]["counts"][0]["drop_reason"], "NO_SOCKET");
+        assert_eq!(json["tables"][1]["counts"][0]["count"], 2);
+        assert_eq!(json["tables"][1]["counts"].as_array().unwrap().len(), 2);
+
+        let mut out = Vec::new();
+        stats.write_text(&mut out)?;
+        let out = String::from_utf8(out)?;
+        assert!(out.contains("skb:kfree_skb      3"));
+        Ok(())
+    }
+}
@@ -2,7 +2,11 @@ use std::io::{ErrorKind, Write};
 
 use anyhow::Result;
 
-use crate::events::*;
+use super::flow::{split_packets, FlowKey};
+use crate::events::{
+    file::{write_binary_header, write_binary_record, FileFormat, FileType},
+    *,
+};
 
 /// Select the format to follow when printing events with `PrintEvent`.
 pub(crate) enum PrintEventFormat {
@@ -11,21 +15,54 @@ pub(crate) enum PrintEventFormat {
     Text(DisplayFormat),
     /// Json: display the event as JSON.
     Json,
+    /// Binary: write the event in the compact binary format. A file header is
+    /// written before the first event.
+    Binary,
+}
+
+impl From<FileFormat> for PrintEventFormat {
+    fn from(format: FileFormat) -> Self {
+        match format {
+            FileFormat::Json => PrintEventFormat::Json,
+            FileFormat::Binary => PrintEventFormat::Binary,
+        }
+    }
+}
+
+/// Common interface of event consumers writing events to an output. This
+/// allows handling a set of different outputs at once.
+pub(crate) trait EventOutput {
+    /// Process events one by one.
+    fn process_one(&mut self, e: &Event) -> Result<()>;
+
+    /// Flush underlying writers.
+    fn flush(&mut self) -> Result<()>;
 }
 
 /// Handles event individually and write to a `Write`.
 pub(crate) struct PrintEvent {
     writer: Box<dyn Write>,
     format: PrintEventFormat,
+    /// Was the binary header written already?
+    header: bool,
+    /// Buffer reused to encode binary records.
+    buf: Vec<u8>,
 }
 
 impl PrintEvent {
     pub(crate) fn new(writer: Box<dyn Write>, format: PrintEventFormat) -> Self {
-        Self { writer, format }
+        Self {
+            writer,
+            format,
+            header: false,
+            buf: Vec::new(),
+        }
     }
+}
 
+impl EventOutput for PrintEvent {
     /// Process events one by one (format & print).
-    pub(crate) fn process_one(&mut self, e: &Event) -> Result<()> {
+    fn process_one(&mut self, e: &Event) -> Result<()> {
         match self.format {
             PrintEventFormat::Text(ref mut format) => {
                 if let Some(common) = e.get_section::<StartupEvent>(SectionId::Startup) {
@@ -50,13 +87,23 @@ impl PrintEvent {
                 event.push(b'\n');
                 self.writer.write_all(&event)?;
             }
+            PrintEventFormat::Binary => {
+                if !self.header {
+                    write_binary_header(&mut self.writer, &FileType::Event)?;
+                    self.header = true;
+                }
+
+                self.buf.clear();
+                e.to_binary(&mut self.buf)?;
+                write_binary_record(&mut self.writer, &self.buf)?;
+            }
         }
 
         Ok(())
     }
 
     /// Flush underlying writers.
-    pub(crate) fn flush(&mut self) -> Result<()> {
+    fn flush(&mut self) -> Result<()> {
         Ok(self.writer.flush()?)
     }
 }
@@ -65,11 +112,20 @@ impl PrintEvent {
 pub(crate) struct PrintSeries {
     writer: Box<dyn Write>,
     format: PrintEventFormat,
+    /// Was the binary header written already?
+    header: bool,
+    /// Buffer reused to encode binary records.
+    buf: Vec<u8>,
 }
 
 impl PrintSeries {
     pub(crate) fn new(writer: Box<dyn Write>, format: PrintEventFormat) -> Self {
-        Self { writer, format }
+        Self {
+            writer,
+            format,
+            header: false,
+            buf: Vec::new(),
+        }
     }
 
     /// Process events one by one (format & print).
@@ -77,23 +133,43 @@ impl PrintSeries {
         let mut content = String::new();
         match self.format {
             PrintEventFormat::Text(ref mut format) => {
+                let packets = split_packets(series);
                 let mut fconf = FormatterConf::new();
-                let mut first = true;
 
-                for event in series.events.iter() {
-                    if let Some(common) = event.get_section::<StartupEvent>(SectionId::Startup) {
-                        format.monotonic_offset = Some(common.clock_monotonic_offset);
+                // Series holding more than one packet are flows, see
+                // `FlowGrouper`. Packets are then nested under a flow header.
+                if packets.len() > 1 {
+                    if let Some(key) = FlowKey::from_series(series) {
+                        content.push_str(&format!("flow {key} ({} packets)\n", packets.len()));
+                        fconf.inc_level(2);
                     }
+                }
+
+                for packet in packets.iter() {
+                    let mut first = true;
+
+                    for event in packet.iter() {
+                        if let Some(common) = event.get_section::<StartupEvent>(SectionId::Startup)
+                        {
+                            format.monotonic_offset = Some(common.clock_monotonic_offset);
+                        }
 
-                    content.push_str(&format!("{}", event.display(format, &fconf)));
-                    if !content.is_empty() {
-                        content.push('\n');
-                        if first {
-                            first = false;
-                            fconf.inc_level(4);
-                            fconf.set_item(Some('↳'));
+                        let len = content.len();
+                        content.push_str(&format!("{}", event.display(format, &fconf)));
+                        if content.len() != len {
+                            content.push('\n');
+                            if first {
+                                first = false;
+                                fconf.inc_level(4);
+                                fconf.set_item(Some('↳'));
+                            }
                         }
                     }
+
+                    if !first {
+                        fconf.reset_level();
+                        fconf.set_item(None);
+                    }
                 }
 
                 if !content.is_empty() {
@@ -111,6 +187,16 @@ impl PrintSeries {
                 event.push(b'\n');
                 self.writer.write_all(&event)?;
             }
+            PrintEventFormat::Binary => {
+                if !self.header {
+                    write_binary_header(&mut self.writer, &FileType::Series)?;
+                    self.header = true;
+                }
+
+                self.buf.clear();
+                series.to_binary(&mut self.buf)?;
+                write_binary_record(&mut self.writer, &self.buf)?;
+            }
         }
 
         Ok(())
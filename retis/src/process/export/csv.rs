@@ -0,0 +1,146 @@
+//! # Csv
+//!
+//! Exports selected fields of events as CSV, one row per event with a header
+//! row. When the input is sorted, a first column holds the index of the series
+//! of each event. Fields missing from an event are left empty.
+
+use std::{io::Write, str::FromStr};
+
+use anyhow::Result;
+
+use super::Exporter;
+use crate::events::*;
+
+/// Field exported as a CSV column, keeping its name as given by the user for
+/// the header row.
+#[derive(Clone, Debug)]
+pub(crate) struct CsvField {
+    name: String,
+    path: FieldPath,
+}
+
+impl FromStr for CsvField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self {
+            name: s.to_string(),
+            path: FieldPath::from_str(s)?,
+        })
+    }
+}
+
+pub(crate) struct Csv {
+    writer: Box<dyn Write>,
+    /// Fields to export, one per column.
+    fields: Vec<CsvField>,
+    /// Add a column holding the series index.
+    series: bool,
+    /// Was the header row written already?
+    header: bool,
+}
+
+impl Csv {
+    pub(crate) fn new(writer: Box<dyn Write>, fields: Vec<CsvField>, series: bool) -> Self {
+        Self {
+            writer,
+            fields,
+            series,
+            header: false,
+        }
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        if self.header {
+            return Ok(());
+        }
+        self.header = true;
+
+        let mut row: Vec<String> = self.fields.iter().map(|f| escape(&f.name)).collect();
+        if self.series {
+            row.insert(0, "series".to_string());
+        }
+        Ok(writeln!(self.writer, "{}", row.join(","))?)
+    }
+}
+
+/// Quote a CSV value if needed.
+fn escape(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+impl Exporter for Csv {
+    fn process(&mut self, events: &[Event], series: Option<usize>) -> Result<()> {
+        self.write_header()?;
+
+        for event in events.iter() {
+            let mut fields = EventFields::new(event);
+            let mut row: Vec<String> = self
+                .fields
+                .iter()
+                .map(|f| {
+                    fields
+                        .get(&f.path)
+                        .map(|v| escape(&v.to_string()))
+                        .unwrap_or_default()
+                })
+                .collect();
+            if self.series {
+                row.insert(0, series.map(|s| s.to_string()).unwrap_or_default());
+            }
+            writeln!(self.writer, "{}", row.join(","))?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_header()?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::{export::tests::Output, tests::TestEvent};
+
+    fn event(timestamp: u64, reason: Option<&str>) -> Event {
+        let event = TestEvent::new().common(timestamp);
+        match reason {
+            Some(reason) => event.drop(reason),
+            None => event,
+        }
+        .build()
+    }
+
+    #[test]
+    fn csv() -> Result<()> {
+        let fields = vec![
+            CsvField::from_str("common.timestamp")?,
+            CsvField::from_str("skb_drop.drop_reason")?,
+        ];
+
+        let output = Output::default();
+        let mut csv = Csv::new(Box::new(output.clone()), fields.clone(), false);
+        csv.process(&[event(1, Some("NO_SOCKET"))], None)?;
+        csv.process(&[event(2, None)], None)?;
+        csv.finish()?;
+        assert_eq!(
+            output.to_string(),
+            "common.timestamp,skb_drop.drop_reason\n1,NO_SOCKET\n2,\n"
+        );
+
+        let output = Output::default();
+        let mut csv = Csv::new(Box::new(output.clone()), fields, true);
+        csv.process(&[event(1, None), event(2, Some("a,\"b\""))], Some(3))?;
+        csv.finish()?;
+        assert_eq!(
+            output.to_string(),
+            "series,common.timestamp,skb_drop.drop_reason\n3,1,\n3,2,\"a,\"\"b\"\"\"\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! # Folded
+//!
+//! Exports the stack traces of events in the folded-stack format, one line per
+//! unique stack with its number of occurrences, as used to generate flame
+//! graphs (e.g. "ip_rcv;ip_rcv_finish;kfree_skb_reason 42").
+
+use std::{collections::HashMap, io::Write};
+
+use anyhow::Result;
+
+use super::Exporter;
+use crate::{events::*, process::cli::StatsKey};
+
+pub(crate) struct FoldedStacks {
+    writer: Box<dyn Write>,
+    /// Keys whose values are added as root frames, e.g. to split stacks by
+    /// probe or drop reason.
+    keys: Vec<StatsKey>,
+    /// Keep the offsets in the symbols (e.g. "kfree_skb_reason+0x4").
+    offsets: bool,
+    stacks: HashMap<String, u64>,
+}
+
+impl FoldedStacks {
+    pub(crate) fn new(writer: Box<dyn Write>, keys: Vec<StatsKey>, offsets: bool) -> Self {
+        Self {
+            writer,
+            keys,
+            offsets,
+            stacks: HashMap::new(),
+        }
+    }
+
+    /// Get the folded stack of an event, if it has a stack trace.
+    fn fold(&self, event: &Event) -> Option<String> {
+        let stack = event
+            .get_section::<KernelEvent>(SectionId::Kernel)?
+            .stack_trace
+            .as_ref()?;
+
+        // Stack traces start with the innermost frame, while folded stacks
+        // start from the root.
+        let frames = stack.raw().iter().rev().map(|sym| match self.offsets {
+            true => sym.as_str(),
+            false => sym.split_once('+').map(|(sym, _)| sym).unwrap_or(sym),
+        });
+
+        let folded: Vec<String> = self
+            .keys
+            .iter()
+            .filter_map(|k| k.value(event))
+            .chain(frames.map(String::from))
+            .collect();
+        Some(folded.join(";"))
+    }
+}
+
+impl Exporter for FoldedStacks {
+    fn process(&mut self, events: &[Event], _: Option<usize>) -> Result<()> {
+        for event in events.iter() {
+            if let Some(folded) = self.fold(event) {
+                *self.stacks.entry(folded).or_default() += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+
+        for (stack, count) in stacks.iter() {
+            writeln!(self.writer, "{stack} {count}")?;
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::{export::tests::Output, tests::TestEvent};
+
+    fn event(stack: &[&str], reason: Option<&str>) -> Event {
+        let event = TestEvent::new().section(
+            SectionId::Kernel,
+            KernelEvent {
+                symbol: "skb:kfree_skb".to_string(),
+                probe_type: "raw_tracepoint".to_string(),
+                stack_trace: Some(StackTrace(stack.iter().map(|s| s.to_string()).collect())),
+            },
+        );
+        match reason {
+            Some(reason) => event.drop(reason),
+            None => event,
+        }
+        .build()
+    }
+
+    #[test]
+    fn folded() -> Result<()> {
+        let output = Output::default();
+        let mut folded =
+            FoldedStacks::new(Box::new(output.clone()), vec![StatsKey::DropReason], false);
+
+        folded.process(
+            &[
+                event(&["kfree_skb_reason+0x4", "ip_rcv+0x1f"], Some("NO_SOCKET")),
+                event(&["kfree_skb_reason+0x4", "ip_rcv+0x2a"], Some("NO_SOCKET")),
+                event(&["consume_skb+0x8", "tcp_rcv+0x10"], None),
+            ],
+            None,
+        )?;
+        folded.finish()?;
+
+        assert_eq!(
+            output.to_string(),
+            "NO_SOCKET;ip_rcv;kfree_skb_reason 2\ntcp_rcv;consume_skb 1\n"
+        );
+        Ok(())
+    }
+}
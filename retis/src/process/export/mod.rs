@@ -0,0 +1,48 @@
+//! # Export
+//!
+//! Exporters converting stored events to formats understood by external
+//! tools. They are used by the export command.
+
+use anyhow::Result;
+
+use crate::events::Event;
+
+pub(crate) mod csv;
+pub(crate) mod folded;
+pub(crate) mod perfetto;
+
+/// Common interface of the exporters.
+pub(crate) trait Exporter {
+    /// Export a set of events: a series when the input is sorted, in which
+    /// case `series` is its index, or a single event otherwise.
+    fn process(&mut self, events: &[Event], series: Option<usize>) -> Result<()>;
+
+    /// Write any pending data and flush the underlying writer.
+    fn finish(&mut self) -> Result<()>;
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::{cell::RefCell, io, rc::Rc};
+
+    /// Writer keeping the written data available for inspection.
+    #[derive(Clone, Default)]
+    pub(crate) struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl std::fmt::Display for Output {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", String::from_utf8_lossy(&self.0.borrow()))
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! # Perfetto
+//!
+//! Exports events in the Chrome trace event JSON format, which can be opened
+//! in Perfetto (or chrome://tracing). Each tracked packet (series) is shown as
+//! a track, events of untracked packets sharing a single one, and events are
+//! also shown on a track per CPU. Kprobe and kretprobe pairs of a packet are
+//! shown as slices, other events as instants.
+
+use std::{collections::HashSet, io::Write};
+
+use anyhow::Result;
+use serde_json::json;
+
+use super::Exporter;
+use crate::{events::*, process::cli::StatsKey};
+
+/// Process holding the packet tracks.
+const PACKETS_PID: u32 = 1;
+/// Process holding the CPU tracks.
+const CPUS_PID: u32 = 2;
+/// Track of untracked packets.
+const UNTRACKED_TID: usize = 0;
+
+pub(crate) struct PerfettoTrace {
+    writer: Box<dyn Write>,
+    /// Was the beginning of the trace written already?
+    started: bool,
+    /// Was the untracked packets track named already?
+    untracked: bool,
+    /// CPUs whose track was named already.
+    cpus: HashSet<u32>,
+}
+
+impl PerfettoTrace {
+    pub(crate) fn new(writer: Box<dyn Write>) -> Self {
+        Self {
+            writer,
+            started: false,
+            untracked: false,
+            cpus: HashSet::new(),
+        }
+    }
+
+    /// Write a trace event.
+    fn write(&mut self, event: &serde_json::Value) -> Result<()> {
+        match self.started {
+            true => self.writer.write_all(b",\n")?,
+            false => {
+                self.started = true;
+                self.writer.write_all(b"{\"traceEvents\":[\n")?;
+                serde_json::to_writer(
+                    &mut self.writer,
+                    &name_event("process", PACKETS_PID, 0, "packets"),
+                )?;
+                self.writer.write_all(b",\n")?;
+                serde_json::to_writer(
+                    &mut self.writer,
+                    &name_event("process", CPUS_PID, 0, "CPUs"),
+                )?;
+                self.writer.write_all(b",\n")?;
+            }
+        }
+        Ok(serde_json::to_writer(&mut self.writer, event)?)
+    }
+}
+
+/// Build a metadata event naming a process or a thread (track).
+fn name_event(kind: &str, pid: u32, tid: usize, name: &str) -> serde_json::Value {
+    json!({
+        "name": format!("{kind}_name"),
+        "ph": "M",
+        "pid": pid,
+        "tid": tid,
+        "args": {"name": name},
+    })
+}
+
+/// Get the duration of the kprobe events of a series which have a matching
+/// kretprobe event (same symbol) later in the series. Returns the duration of
+/// each kprobe event and whether each event is a matched kretprobe one.
+fn match_kretprobes(events: &[Event]) -> (Vec<Option<u64>>, Vec<bool>) {
+    let mut durations = vec![None; events.len()];
+    let mut matched = vec![false; events.len()];
+    let mut pending: Vec<(usize, &str, u64)> = Vec::new();
+
+    for (idx, event) in events.iter().enumerate() {
+        let (kernel, common) = match (
+            event.get_section::<KernelEvent>(SectionId::Kernel),
+            event.get_section::<CommonEvent>(SectionId::Common),
+        ) {
+            (Some(kernel), Some(common)) => (kernel, common),
+            _ => continue,
+        };
+
+        match kernel.probe_type.as_str() {
+            "kprobe" => pending.push((idx, &kernel.symbol, common.timestamp)),
+            "kretprobe" => {
+                if let Some(pos) = pending.iter().rposition(|p| p.1 == kernel.symbol) {
+                    let (start, _, timestamp) = pending.remove(pos);
+                    durations[start] = Some(common.timestamp.saturating_sub(timestamp));
+                    matched[idx] = true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    (durations, matched)
+}
+
+impl Exporter for PerfettoTrace {
+    fn process(&mut self, events: &[Event], series: Option<usize>) -> Result<()> {
+        let tracking = events
+            .first()
+            .and_then(|e| e.get_section::<TrackingInfo>(SectionId::Tracking))
+            .map(|t| t.skb.tracking_id());
+
+        // Packet tracks are identified by the series index, offset by one to
+        // keep the first track for untracked packets.
+        let tid = match (tracking, series) {
+            (Some(id), Some(series)) => {
+                self.write(&name_event(
+                    "thread",
+                    PACKETS_PID,
+                    series + 1,
+                    &format!("#{id:x}"),
+                ))?;
+                series + 1
+            }
+            _ => {
+                if !self.untracked {
+                    self.untracked = true;
+                    self.write(&name_event(
+                        "thread",
+                        PACKETS_PID,
+                        UNTRACKED_TID,
+                        "untracked",
+                    ))?;
+                }
+                UNTRACKED_TID
+            }
+        };
+
+        let (durations, matched) = match_kretprobes(events);
+        for (idx, event) in events.iter().enumerate() {
+            let (symbol, common) = match (
+                StatsKey::Symbol.value(event),
+                event.get_section::<CommonEvent>(SectionId::Common),
+            ) {
+                (Some(symbol), Some(common)) => (symbol, common),
+                _ => continue,
+            };
+            // Kretprobe events are part of the slice of their kprobe one.
+            if matched[idx] {
+                continue;
+            }
+
+            // Timestamps are given in microseconds.
+            let mut trace = json!({
+                "name": symbol,
+                "ts": common.timestamp as f64 / 1000.0,
+                "pid": PACKETS_PID,
+                "tid": tid,
+                "args": event.to_json(),
+            });
+            match durations[idx] {
+                Some(duration) => {
+                    trace["ph"] = json!("X");
+                    trace["dur"] = json!(duration as f64 / 1000.0);
+                }
+                None => {
+                    trace["ph"] = json!("i");
+                    trace["s"] = json!("t");
+                }
+            }
+            self.write(&trace)?;
+
+            if let Some(cpu) = common.smp_id {
+                if self.cpus.insert(cpu) {
+                    self.write(&name_event(
+                        "thread",
+                        CPUS_PID,
+                        cpu as usize,
+                        &format!("CPU {cpu}"),
+                    ))?;
+                }
+
+                if let Some(task) = &common.task {
+                    trace["name"] = json!(format!("{symbol} ({} {})", task.comm, task.pid));
+                }
+                trace["pid"] = json!(CPUS_PID);
+                trace["tid"] = json!(cpu);
+                self.write(&trace)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if !self.started {
+            self.writer.write_all(b"{\"traceEvents\":[")?;
+        }
+        self.writer
+            .write_all(b"\n],\"displayTimeUnit\":\"ns\"}\n")?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::{export::tests::Output, tests::TestEvent};
+
+    fn event(symbol: &str, probe_type: &str, timestamp: u64) -> Event {
+        TestEvent::new()
+            .section(
+                SectionId::Common,
+                CommonEvent {
+                    timestamp,
+                    smp_id: Some(3),
+                    task: Some(TaskEvent {
+                        pid: 42,
+                        tgid: 42,
+                        comm: "curl".to_string(),
+                    }),
+                },
+            )
+            .kernel(symbol, probe_type)
+            .tracking(0x10, 0x20, 0)
+            .build()
+    }
+
+    #[test]
+    fn perfetto() -> Result<()> {
+        let output = Output::default();
+        let mut trace = PerfettoTrace::new(Box::new(output.clone()));
+
+        trace.process(
+            &[
+                event("ip_rcv", "kprobe", 1000),
+                event("net:netif_receive_skb", "raw_tracepoint", 2000),
+                event("ip_rcv", "kretprobe", 5000),
+            ],
+            Some(0),
+        )?;
+        trace.finish()?;
+
+        let json: serde_json::Value = serde_json::from_str(&output.to_string())?;
+        let events: Vec<_> = json["traceEvents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|e| e["ph"] != "M" && e["pid"] == PACKETS_PID)
+            .collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["name"], "ip_rcv");
+        assert_eq!(events[0]["ph"], "X");
+        assert_eq!(events[0]["ts"], 1.0);
+        assert_eq!(events[0]["dur"], 4.0);
+        assert_eq!(events[0]["tid"], 1);
+        assert_eq!(events[1]["ph"], "i");
+
+        let cpu = json["traceEvents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["ph"] != "M" && e["pid"] == CPUS_PID)
+            .unwrap();
+        assert_eq!(cpu["tid"], 3);
+        assert_eq!(cpu["name"], "ip_rcv (curl 42)");
+        Ok(())
+    }
+}
@@ -0,0 +1,431 @@
+//! # Filter
+//!
+//! Post-processing filter expressions, evaluated on the fields of stored
+//! events. Expressions compare event fields, addressed by their path (e.g.
+//! `skb.ip.daddr`), to values and can be combined using `&&`, `||`, `!` and
+//! parenthesis, e.g.:
+//!
+//! `skb.ip.daddr == "10.0.0.1" && skb-drop.drop_reason == NETFILTER_DROP || kernel.symbol =~ "tcp_*"`
+//!
+//! Supported operators are `==`, `!=`, `<`, `<=`, `>`, `>=` and `=~`, `!~`
+//! which match a glob pattern (`*` and `?` wildcards). A path given without
+//! an operator matches events in which the field is set.
+
+use std::{cmp::Ordering, str::FromStr};
+
+use anyhow::{bail, Result};
+
+use crate::events::*;
+
+/// Comparison operators.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// Field path or bare value.
+    Word(String),
+    /// Quoted value.
+    Str(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+/// Split an expression into tokens.
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let next = chars.peek().copied();
+        let token = match (c, next) {
+            (c, _) if c.is_whitespace() => continue,
+            ('(', _) => Token::LParen,
+            (')', _) => Token::RParen,
+            ('&', Some('&')) => Token::And,
+            ('|', Some('|')) => Token::Or,
+            ('=', Some('=')) => Token::Op(Op::Eq),
+            ('=', Some('~')) => Token::Op(Op::Match),
+            ('!', Some('=')) => Token::Op(Op::Ne),
+            ('!', Some('~')) => Token::Op(Op::NotMatch),
+            ('<', Some('=')) => Token::Op(Op::Le),
+            ('>', Some('=')) => Token::Op(Op::Ge),
+            ('<', _) => Token::Op(Op::Lt),
+            ('>', _) => Token::Op(Op::Gt),
+            ('!', _) => Token::Not,
+            ('"', _) => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => value.push(c),
+                            None => bail!("Unterminated string in filter"),
+                        },
+                        Some(c) => value.push(c),
+                        None => bail!("Unterminated string in filter"),
+                    }
+                }
+                tokens.push(Token::Str(value));
+                continue;
+            }
+            (c, _) if is_word_char(c) => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.peek() {
+                    if !is_word_char(*c) {
+                        break;
+                    }
+                    word.push(*c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+                continue;
+            }
+            (c, _) => bail!("Unexpected character '{c}' in filter"),
+        };
+
+        // Two-character tokens.
+        if matches!(
+            token,
+            Token::And
+                | Token::Or
+                | Token::Op(Op::Eq | Op::Match | Op::Ne | Op::NotMatch | Op::Le | Op::Ge)
+        ) {
+            chars.next();
+        }
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-.:*?/+".contains(c)
+}
+
+/// Filter expression.
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// Field path, with an optional comparison.
+    Field(FieldPath, Option<(Op, String)>),
+}
+
+/// Recursive descent parser of filter expressions.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::LParen) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => bail!("Missing closing parenthesis in filter"),
+                }
+            }
+            Some(Token::Word(path)) => {
+                let path = FieldPath::from_str(&path)?;
+                let cmp = match self.peek() {
+                    Some(Token::Op(op)) => {
+                        let op = *op;
+                        self.next();
+                        match self.next() {
+                            Some(Token::Word(value) | Token::Str(value)) => Some((op, value)),
+                            _ => bail!("Missing value after comparison operator in filter"),
+                        }
+                    }
+                    _ => None,
+                };
+                Ok(Expr::Field(path, cmp))
+            }
+            Some(token) => bail!("Unexpected token {token:?} in filter"),
+            None => bail!("Unexpected end of filter"),
+        }
+    }
+}
+
+/// Compare a field value to a filter value. Numbers are compared numerically,
+/// strings lexicographically.
+fn compare(value: &FieldValue, target: &str) -> Option<Ordering> {
+    match value {
+        FieldValue::Unsigned(v) => match target.parse::<u64>() {
+            Ok(t) => Some(v.cmp(&t)),
+            Err(_) => (*v as f64).partial_cmp(&target.parse::<f64>().ok()?),
+        },
+        FieldValue::Signed(v) => match target.parse::<i64>() {
+            Ok(t) => Some(v.cmp(&t)),
+            Err(_) => (*v as f64).partial_cmp(&target.parse::<f64>().ok()?),
+        },
+        FieldValue::Float(v) => v.partial_cmp(&target.parse::<f64>().ok()?),
+        FieldValue::String(s) => Some(s.as_str().cmp(target)),
+        FieldValue::Bool(b) => Some(b.cmp(&target.parse::<bool>().ok()?)),
+        _ => None,
+    }
+}
+
+/// Match a string against a glob pattern supporting `*` and `?`.
+fn glob_match(pattern: &str, s: &str) -> bool {
+    let (p, s): (Vec<char>, Vec<char>) = (pattern.chars().collect(), s.chars().collect());
+    let (mut pi, mut si) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while si < s.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == s[si]) {
+            pi += 1;
+            si += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, si));
+            pi += 1;
+        } else if let Some((spi, ssi)) = star {
+            pi = spi + 1;
+            si = ssi + 1;
+            star = Some((spi, ssi + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+impl Expr {
+    fn eval(&self, fields: &mut EventFields) -> bool {
+        match self {
+            Expr::And(l, r) => l.eval(fields) && r.eval(fields),
+            Expr::Or(l, r) => l.eval(fields) || r.eval(fields),
+            Expr::Not(e) => !e.eval(fields),
+            Expr::Field(path, cmp) => {
+                let value = match fields.get(path) {
+                    Some(value) => value,
+                    None => return false,
+                };
+
+                let (op, target) = match cmp {
+                    Some(cmp) => cmp,
+                    None => return true,
+                };
+
+                match op {
+                    Op::Match | Op::NotMatch => {
+                        glob_match(target, &value.to_string()) == (*op == Op::Match)
+                    }
+                    op => match compare(&value, target) {
+                        Some(ord) => match op {
+                            Op::Eq => ord == Ordering::Equal,
+                            Op::Ne => ord != Ordering::Equal,
+                            Op::Lt => ord == Ordering::Less,
+                            Op::Le => ord != Ordering::Greater,
+                            Op::Gt => ord == Ordering::Greater,
+                            Op::Ge => ord != Ordering::Less,
+                            _ => unreachable!(),
+                        },
+                        // Values of different types are never equal.
+                        None => *op == Op::Ne,
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Filter matching events based on an expression.
+#[derive(Clone, Debug)]
+pub(crate) struct EventFilter {
+    expr: Expr,
+}
+
+impl FromStr for EventFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected token {token:?} in filter");
+        }
+
+        Ok(Self { expr })
+    }
+}
+
+impl EventFilter {
+    /// Does an event match the filter? Startup events always match, as they
+    /// are needed to process the other events.
+    pub(crate) fn matches(&self, event: &Event) -> bool {
+        event
+            .get_section::<StartupEvent>(SectionId::Startup)
+            .is_some()
+            || self.expr.eval(&mut EventFields::new(event))
+    }
+
+    /// Does a series match the filter? A series matches if any of its events
+    /// does. Series not related to packets (startup, lost events and markers)
+    /// always match, as they are needed to interpret the other series.
+    pub(crate) fn matches_series(&self, series: &EventSeries) -> bool {
+        series.events.iter().all(|e| {
+            e.sections()
+                .any(|s| matches!(s, SectionId::Startup | SectionId::Lost | SectionId::Marker))
+        }) || series.events.iter().any(|e| self.matches(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tests::TestEvent;
+
+    fn event() -> Event {
+        TestEvent::new()
+            .common(42)
+            .kernel("tcp_v4_rcv", "kprobe")
+            .drop("NETFILTER_DROP")
+            .build()
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("tcp_*", "tcp_v4_rcv"));
+        assert!(glob_match("*_rcv", "tcp_v4_rcv"));
+        assert!(glob_match("tcp_v?_rcv", "tcp_v4_rcv"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("udp_*", "tcp_v4_rcv"));
+        assert!(!glob_match("tcp", "tcp_v4_rcv"));
+    }
+
+    #[test]
+    fn parse() {
+        for f in [
+            "kernel",
+            "kernel.symbol == tcp_v4_rcv",
+            "skb.ip.daddr == \"10.0.0.1\" && skb_drop.drop_reason == \"NETFILTER_DROP\" || kernel.symbol =~ \"tcp_*\"",
+            "!(common.timestamp < 10 || common.timestamp >= 100)",
+        ] {
+            assert!(EventFilter::from_str(f).is_ok(), "{f}");
+        }
+
+        for f in [
+            "",
+            "foo.bar == 1",
+            "kernel.symbol ==",
+            "(kernel.symbol == tcp_v4_rcv",
+            "kernel.symbol == \"tcp_v4_rcv",
+            "kernel.symbol == tcp_v4_rcv kernel",
+            "kernel..symbol",
+        ] {
+            assert!(EventFilter::from_str(f).is_err(), "{f}");
+        }
+    }
+
+    #[test]
+    fn matches() -> Result<()> {
+        let event = event();
+
+        for (f, res) in [
+            ("kernel", true),
+            ("skb", false),
+            ("kernel.symbol == tcp_v4_rcv", true),
+            ("kernel.symbol != tcp_v4_rcv", false),
+            ("kernel.symbol =~ \"tcp_*\"", true),
+            ("kernel.symbol !~ \"tcp_*\"", false),
+            ("skb_drop.drop_reason == NETFILTER_DROP", true),
+            ("skb-drop.drop_reason == NO_SOCKET", false),
+            ("common.timestamp == 42", true),
+            ("common.timestamp > 41 && common.timestamp <= 42", true),
+            ("common.timestamp < 42", false),
+            ("common.timestamp == foo", false),
+            (
+                "skb.ip.daddr == \"10.0.0.1\" || kernel.symbol =~ \"tcp_*\"",
+                true,
+            ),
+            (
+                "skb.ip.daddr == \"10.0.0.1\" && kernel.symbol =~ \"tcp_*\"",
+                false,
+            ),
+            ("!skb && !(kernel.symbol == udp_rcv)", true),
+        ] {
+            assert_eq!(EventFilter::from_str(f)?.matches(&event), res, "{f}");
+        }
+
+        let startup = TestEvent::new()
+            .section(
+                SectionId::Startup,
+                StartupEvent {
+                    retis_version: "test".to_string(),
+                    clock_monotonic_offset: TimeSpec::new(0, 0),
+                    sampling: None,
+                },
+            )
+            .build();
+        assert!(EventFilter::from_str("skb")?.matches(&startup));
+
+        let marker = TestEvent::new()
+            .common(0)
+            .section(
+                SectionId::Marker,
+                MarkerEvent {
+                    message: "test".to_string(),
+                },
+            )
+            .build();
+        let filter = EventFilter::from_str("skb")?;
+        assert!(filter.matches_series(&EventSeries {
+            events: vec![marker]
+        }));
+        assert!(!filter.matches_series(&EventSeries {
+            events: vec![event]
+        }));
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+//! # Flow
+//!
+//! Groups event series (one per packet) by flow, identified by their
+//! normalized 5-tuple so both directions of a connection end up in the same
+//! flow.
+
+use std::{collections::HashMap, fmt};
+
+use crate::events::*;
+
+/// L4 protocol numbers used in flow keys.
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ICMPV6: u8 = 58;
+
+/// Normalized 5-tuple identifying a flow. Endpoints are ordered so that both
+/// directions of a connection share the same key.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct FlowKey {
+    /// L4 protocol number.
+    pub(crate) proto: u8,
+    /// Lowest (address, port) endpoint.
+    pub(crate) a: (String, u16),
+    /// Highest (address, port) endpoint.
+    pub(crate) b: (String, u16),
+}
+
+impl FlowKey {
+    pub(crate) fn new(proto: u8, src: (String, u16), dst: (String, u16)) -> Self {
+        let (a, b) = if src <= dst { (src, dst) } else { (dst, src) };
+        Self { proto, a, b }
+    }
+
+    /// Compute the flow key of a series. The conntrack original tuple is used
+    /// when available, otherwise the key is built from the first event having
+    /// IP information. Returns `None` if no event of the series has any.
+    pub(crate) fn from_series(series: &EventSeries) -> Option<Self> {
+        series
+            .events
+            .iter()
+            .find_map(Self::from_ct)
+            .or_else(|| series.events.iter().find_map(Self::from_skb))
+    }
+
+    fn from_ct(event: &Event) -> Option<Self> {
+        let orig = &event.get_section::<CtEvent>(SectionId::Ct)?.base.orig;
+
+        let (proto, sport, dport) = match &orig.proto {
+            CtProto::Tcp(tcp) => (IPPROTO_TCP, tcp.sport, tcp.dport),
+            CtProto::Udp(udp) => (IPPROTO_UDP, udp.sport, udp.dport),
+            CtProto::Icmp(_) => match orig.ip.version {
+                CtIpVersion::V4 => (IPPROTO_ICMP, 0, 0),
+                CtIpVersion::V6 => (IPPROTO_ICMPV6, 0, 0),
+            },
+        };
+
+        Some(Self::new(
+            proto,
+            (orig.ip.src.clone(), sport),
+            (orig.ip.dst.clone(), dport),
+        ))
+    }
+
+    fn from_skb(event: &Event) -> Option<Self> {
+        let skb = event.get_section::<SkbEvent>(SectionId::Skb)?;
+        let ip = skb.ip.as_ref()?;
+
+        let (sport, dport) = match (&skb.tcp, &skb.udp) {
+            (Some(tcp), _) => (tcp.sport, tcp.dport),
+            (_, Some(udp)) => (udp.sport, udp.dport),
+            _ => (0, 0),
+        };
+
+        Some(Self::new(
+            ip.protocol,
+            (ip.saddr.clone(), sport),
+            (ip.daddr.clone(), dport),
+        ))
+    }
+}
+
+impl fmt::Display for FlowKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.proto {
+            IPPROTO_TCP => write!(f, "tcp")?,
+            IPPROTO_UDP => write!(f, "udp")?,
+            IPPROTO_ICMP => write!(f, "icmp")?,
+            IPPROTO_ICMPV6 => write!(f, "icmpv6")?,
+            proto => write!(f, "proto {proto}")?,
+        }
+
+        match self.proto {
+            IPPROTO_TCP | IPPROTO_UDP => {
+                write!(f, " {}.{} <> {}.{}", self.a.0, self.a.1, self.b.0, self.b.1)
+            }
+            _ => write!(f, " {} <> {}", self.a.0, self.b.0),
+        }
+    }
+}
+
+/// Groups packet series by flow. Flows are returned in the order of their
+/// first packet; series without flow information are kept on their own.
+#[derive(Default)]
+pub(crate) struct FlowGrouper {
+    flows: Vec<EventSeries>,
+    index: HashMap<FlowKey, usize>,
+}
+
+impl FlowGrouper {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a packet series to its flow.
+    pub(crate) fn add(&mut self, mut series: EventSeries) {
+        match FlowKey::from_series(&series) {
+            Some(key) => match self.index.get(&key) {
+                Some(idx) => self.flows[*idx].events.append(&mut series.events),
+                None => {
+                    self.index.insert(key, self.flows.len());
+                    self.flows.push(series);
+                }
+            },
+            None => self.flows.push(series),
+        }
+    }
+
+    /// Consume the grouper and return the flows.
+    pub(crate) fn into_flows(self) -> impl Iterator<Item = EventSeries> {
+        self.flows.into_iter()
+    }
+}
+
+/// Split a flow into its packet series, using the tracking information of the
+/// events. Events without tracking information are packets on their own.
+pub(crate) fn split_packets(series: &EventSeries) -> Vec<&[Event]> {
+    let id = |event: &Event| {
+        event
+            .get_section::<TrackingInfo>(SectionId::Tracking)
+            .map(|t| t.skb.tracking_id())
+    };
+
+    let mut packets = Vec::new();
+    let mut start = 0;
+    for i in 1..series.events.len() {
+        let (prev, cur) = (id(&series.events[i - 1]), id(&series.events[i]));
+        if prev.is_none() || prev != cur {
+            packets.push(&series.events[start..i]);
+            start = i;
+        }
+    }
+    if start < series.events.len() {
+        packets.push(&series.events[start..]);
+    }
+    packets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tests::TestEvent;
+
+    fn packet(orig_head: u64, src: (&str, u16), dst: (&str, u16)) -> EventSeries {
+        let event = TestEvent::new()
+            .tracking(orig_head, orig_head, 0)
+            .section(
+                SectionId::Skb,
+                SkbEvent {
+                    ip: Some(SkbIpEvent {
+                        saddr: src.0.to_string(),
+                        daddr: dst.0.to_string(),
+                        version: SkbIpVersion::V4(SkbIpv4Event {
+                            tos: 0,
+                            id: 0,
+                            flags: 0,
+                            offset: 0,
+                        }),
+                        protocol: IPPROTO_TCP,
+                        len: 60,
+                        ttl: 64,
+                        ecn: 0,
+                    }),
+                    tcp: Some(SkbTcpEvent {
+                        sport: src.1,
+                        dport: dst.1,
+                        seq: 0,
+                        ack_seq: 0,
+                        window: 0,
+                        doff: 5,
+                        flags: 0,
+                    }),
+                    ..Default::default()
+                },
+            )
+            .build();
+
+        EventSeries {
+            events: vec![event],
+        }
+    }
+
+    #[test]
+    fn flow_key() {
+        let key = FlowKey::from_series(&packet(1, ("10.0.0.2", 80), ("10.0.0.1", 4242))).unwrap();
+        assert_eq!(
+            key,
+            FlowKey::from_series(&packet(2, ("10.0.0.1", 4242), ("10.0.0.2", 80))).unwrap()
+        );
+        assert_eq!(key.to_string(), "tcp 10.0.0.1.4242 <> 10.0.0.2.80");
+        assert!(FlowKey::from_series(&EventSeries::default()).is_none());
+    }
+
+    #[test]
+    fn group() {
+        let mut grouper = FlowGrouper::new();
+        grouper.add(packet(1, ("10.0.0.1", 4242), ("10.0.0.2", 80)));
+        grouper.add(packet(2, ("10.0.0.1", 4243), ("10.0.0.2", 80)));
+        grouper.add(packet(3, ("10.0.0.2", 80), ("10.0.0.1", 4242)));
+        grouper.add(EventSeries::default());
+
+        let flows: Vec<_> = grouper.into_flows().collect();
+        assert_eq!(flows.len(), 3);
+        assert_eq!(flows[0].events.len(), 2);
+        assert_eq!(split_packets(&flows[0]).len(), 2);
+        assert_eq!(flows[1].events.len(), 1);
+        assert_eq!(split_packets(&flows[1]).len(), 1);
+        assert!(flows[2].events.is_empty());
+    }
+}
@@ -5,5 +5,83 @@
 pub(crate) mod cli;
 
 pub(crate) mod display;
+pub(crate) mod export;
+pub(crate) mod filter;
+pub(crate) mod flow;
 pub(crate) mod series;
 pub(crate) mod tracking;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::events::*;
+
+    /// Builder of the events used in processing tests.
+    #[derive(Default)]
+    pub(crate) struct TestEvent(Event);
+
+    impl TestEvent {
+        pub(crate) fn new() -> Self {
+            Self::default()
+        }
+
+        pub(crate) fn section<T: EventSection + 'static>(
+            mut self,
+            id: SectionId,
+            section: T,
+        ) -> Self {
+            self.0.insert_section(id, Box::new(section)).unwrap();
+            self
+        }
+
+        pub(crate) fn common(self, timestamp: u64) -> Self {
+            self.section(
+                SectionId::Common,
+                CommonEvent {
+                    timestamp,
+                    ..Default::default()
+                },
+            )
+        }
+
+        pub(crate) fn kernel(self, symbol: &str, probe_type: &str) -> Self {
+            self.section(
+                SectionId::Kernel,
+                KernelEvent {
+                    symbol: symbol.to_string(),
+                    probe_type: probe_type.to_string(),
+                    stack_trace: None,
+                },
+            )
+        }
+
+        /// Tracking section of a packet identified by its original head and
+        /// first timestamp.
+        pub(crate) fn tracking(self, orig_head: u64, timestamp: u64, idx: u32) -> Self {
+            self.section(
+                SectionId::Tracking,
+                TrackingInfo {
+                    skb: SkbTrackingEvent {
+                        orig_head,
+                        timestamp,
+                        skb: orig_head,
+                    },
+                    idx,
+                },
+            )
+        }
+
+        pub(crate) fn drop(self, reason: &str) -> Self {
+            self.section(
+                SectionId::SkbDrop,
+                SkbDropEvent {
+                    subsys: None,
+                    drop_reason: reason.to_string(),
+                },
+            )
+        }
+
+        pub(crate) fn build(self) -> Event {
+            self.0
+        }
+    }
+}
@@ -5,11 +5,29 @@
 //! Events can be added to EventSeries in any order and it will internally arrange them based on
 //! their TrackingInfo.
 
-use std::collections::{BTreeMap, VecDeque};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, VecDeque},
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
+use log::{debug, warn};
 
-use crate::events::{CommonEvent, Event, EventSeries, SectionId, TrackingInfo};
+use super::{
+    display::{PrintEventFormat, PrintSeries},
+    tracking::AddTracking,
+};
+use crate::{
+    events::{
+        file::{FileEventsFactory, FileType},
+        CommonEvent, Event, EventSeries, SectionId, TrackingInfo,
+    },
+    helpers::signals::Running,
+};
 
 #[derive(Default)]
 pub(crate) struct EventSorter {
@@ -107,3 +125,366 @@ impl EventSorter {
         }
     }
 }
+
+/// Call `f` on each series stored in a file. Events of non-sorted files are
+/// sorted in series on the fly using their tracking information, buffering at
+/// most `max_buffer` events (zero meaning the buffer can grow endlessly).
+pub(crate) fn for_each_series<F>(
+    factory: &mut FileEventsFactory,
+    max_buffer: usize,
+    run: &Running,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(EventSeries) -> Result<()>,
+{
+    if matches!(factory.file_type(), FileType::Series) {
+        while run.running() {
+            match factory.next_series()? {
+                Some(series) => f(series)?,
+                None => break,
+            }
+        }
+        return Ok(());
+    }
+
+    let mut sorter = EventSorter::new();
+    let mut tracker = AddTracking::new();
+
+    while run.running() {
+        match factory.next_event()? {
+            Some(mut event) => {
+                tracker.process_one(&mut event)?;
+                sorter.add(event);
+
+                if max_buffer != 0 {
+                    while sorter.len() >= max_buffer {
+                        match sorter.pop_oldest()? {
+                            Some(series) => f(series)?,
+                            None => break,
+                        }
+                    }
+                }
+            }
+            None => break,
+        }
+    }
+    while sorter.len() > 0 {
+        match sorter.pop_oldest()? {
+            Some(series) => f(series)?,
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Key used to order series: the timestamp of the first event of the packet
+/// and, for tracked series, the original head of the packet. Together they
+/// form the tracking id.
+type SeriesKey = (u64, Option<u64>);
+
+fn series_key(series: &EventSeries) -> Result<SeriesKey> {
+    let first = series
+        .events
+        .first()
+        .ok_or_else(|| anyhow!("malformed series: no event"))?;
+
+    Ok(
+        match first.get_section::<TrackingInfo>(SectionId::Tracking) {
+            Some(track) => (track.skb.timestamp, Some(track.skb.orig_head)),
+            None => (
+                first
+                    .get_section::<CommonEvent>(SectionId::Common)
+                    .map(|c| c.timestamp)
+                    .ok_or_else(|| anyhow!("malformed event: no common section"))?,
+                None,
+            ),
+        },
+    )
+}
+
+/// Maximum number of sorted runs merged at once, to bound the number of files
+/// opened at the same time.
+const MAX_FAN_IN: usize = 64;
+
+/// Source of series ordered by key.
+type SeriesSource = Box<dyn Iterator<Item = Result<EventSeries>>>;
+
+/// Opens a sorted run as a source of series.
+fn open_run(path: &Path) -> Result<SeriesSource> {
+    let mut factory = FileEventsFactory::new(path)?;
+    Ok(Box::new(std::iter::from_fn(move || {
+        factory.next_series().transpose()
+    })))
+}
+
+/// K-way merge of sources of series ordered by key, giving the resulting
+/// series to `output` in order. Parts of a tracked series found in multiple
+/// sources are merged back together, in the order of the sources.
+fn merge_sources<F>(mut sources: Vec<SeriesSource>, mut output: F) -> Result<()>
+where
+    F: FnMut(EventSeries) -> Result<()>,
+{
+    // Keep the next series of each source.
+    let mut heads: Vec<Option<EventSeries>> = Vec::with_capacity(sources.len());
+    let mut heap = BinaryHeap::new();
+    for (idx, source) in sources.iter_mut().enumerate() {
+        let head = source.next().transpose()?;
+        if let Some(series) = &head {
+            heap.push(Reverse((series_key(series)?, idx)));
+        }
+        heads.push(head);
+    }
+
+    let mut next = |idx: usize,
+                    heads: &mut Vec<Option<EventSeries>>,
+                    heap: &mut BinaryHeap<Reverse<(SeriesKey, usize)>>|
+     -> Result<EventSeries> {
+        let series = heads[idx]
+            .take()
+            .ok_or_else(|| anyhow!("sorted run {idx} has no pending series"))?;
+        if let Some(head) = sources[idx].next().transpose()? {
+            heap.push(Reverse((series_key(&head)?, idx)));
+            heads[idx] = Some(head);
+        }
+        Ok(series)
+    };
+
+    while let Some(Reverse((key, idx))) = heap.pop() {
+        let mut series = next(idx, &mut heads, &mut heap)?;
+
+        if key.1.is_some() {
+            while let Some(Reverse((other, idx))) = heap.peek().copied() {
+                if other != key {
+                    break;
+                }
+                heap.pop();
+                series
+                    .events
+                    .extend(next(idx, &mut heads, &mut heap)?.events);
+            }
+        }
+
+        output(series)?;
+    }
+
+    Ok(())
+}
+
+/// Sorts events with bounded memory. Events are buffered in an `EventSorter`
+/// and, once `max_events` is reached, the (partially built) series are spilled
+/// to a temporary file ordered by their key. Spilled files are then merged,
+/// series with the same tracking id found in multiple files being
+/// concatenated, so any amount of events can be fully sorted. At most
+/// `MAX_FAN_IN` files are merged at once, using intermediate files if needed.
+pub(crate) struct ExternalSorter {
+    sorter: EventSorter,
+    max_events: usize,
+    /// Directory holding the spilled files.
+    dir: PathBuf,
+    /// Sorted runs, in the order they were spilled.
+    runs: Vec<PathBuf>,
+    /// Number of runs created so far, used to name them.
+    created: usize,
+    fan_in: usize,
+}
+
+impl ExternalSorter {
+    pub(crate) fn new(tmp_dir: &Path, max_events: usize) -> Result<Self> {
+        if max_events == 0 {
+            bail!("The number of events to keep in memory must be greater than 0");
+        }
+
+        let dir = tmp_dir.join(format!("retis-sort-{}", process::id()));
+        fs::create_dir_all(&dir)
+            .or_else(|e| bail!("Could not create directory '{}': {e}", dir.display()))?;
+
+        Ok(Self {
+            sorter: EventSorter::new(),
+            max_events,
+            dir,
+            runs: Vec::new(),
+            created: 0,
+            fan_in: MAX_FAN_IN,
+        })
+    }
+
+    /// Adds an event, spilling the buffered series to disk if needed.
+    pub(crate) fn add(&mut self, event: Event) -> Result<()> {
+        self.sorter.add(event);
+        if self.sorter.len() >= self.max_events {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Empties the in-memory sorter, returning its series ordered by key.
+    fn drain(&mut self) -> Result<Vec<(SeriesKey, EventSeries)>> {
+        let mut series = Vec::new();
+        while let Some(s) = self.sorter.pop_oldest()? {
+            series.push((series_key(&s)?, s));
+        }
+        series.sort_by_key(|(key, _)| *key);
+        Ok(series)
+    }
+
+    /// Creates a new temporary file to write a sorted run to.
+    fn create_run(&mut self) -> Result<(PathBuf, PrintSeries)> {
+        let path = self.dir.join(format!("run.{}", self.created));
+        self.created += 1;
+
+        let file =
+            File::create(&path).or_else(|e| bail!("Could not create '{}': {e}", path.display()))?;
+        Ok((
+            path,
+            PrintSeries::new(Box::new(BufWriter::new(file)), PrintEventFormat::Binary),
+        ))
+    }
+
+    /// Writes the buffered series to a new temporary file.
+    fn spill(&mut self) -> Result<()> {
+        let (path, mut output) = self.create_run()?;
+        debug!(
+            "Spilling {} event(s) to {}",
+            self.sorter.len(),
+            path.display()
+        );
+
+        self.drain()?
+            .iter()
+            .try_for_each(|(_, series)| output.process_one(series))?;
+        output.flush()?;
+
+        self.runs.push(path);
+        Ok(())
+    }
+
+    /// Merges groups of sorted runs into intermediate ones until they can all
+    /// be merged at once, along with the in-memory series.
+    fn reduce_runs(&mut self) -> Result<()> {
+        while self.runs.len() >= self.fan_in {
+            let runs = std::mem::take(&mut self.runs);
+            debug!("Merging {} sorted runs", runs.len());
+
+            for group in runs.chunks(self.fan_in) {
+                if group.len() == 1 {
+                    self.runs.push(group[0].clone());
+                    continue;
+                }
+
+                let (path, mut output) = self.create_run()?;
+                merge_sources(
+                    group
+                        .iter()
+                        .map(|run| open_run(run))
+                        .collect::<Result<_>>()?,
+                    |series| output.process_one(&series),
+                )?;
+                output.flush()?;
+
+                group.iter().try_for_each(|run| {
+                    fs::remove_file(run)
+                        .or_else(|e| bail!("Could not remove '{}': {e}", run.display()))
+                })?;
+                self.runs.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges all the series, in-memory and spilled ones, and gives them to
+    /// `output` in order.
+    pub(crate) fn finish<F>(mut self, output: F) -> Result<()>
+    where
+        F: FnMut(EventSeries) -> Result<()>,
+    {
+        self.reduce_runs()?;
+
+        let mut sources = self
+            .runs
+            .iter()
+            .map(|run| open_run(run))
+            .collect::<Result<Vec<_>>>()?;
+        // The remaining in-memory series are the most recent ones, so they
+        // come last.
+        sources.push(Box::new(self.drain()?.into_iter().map(|(_, s)| Ok(s))));
+
+        merge_sources(sources, output)
+    }
+}
+
+impl Drop for ExternalSorter {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            warn!(
+                "Could not remove temporary directory '{}': {e}",
+                self.dir.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::process::tests::TestEvent;
+
+    fn event(timestamp: u64, tracking: Option<(u64, u32)>) -> Event {
+        let event = TestEvent::new().common(timestamp);
+        match tracking {
+            Some((ts, idx)) => event.tracking(0xff, ts, idx),
+            None => event,
+        }
+        .build()
+    }
+
+    #[test]
+    fn external_sort() -> Result<()> {
+        // Merge all runs at once, then in multiple passes as there are more
+        // runs than the fan-in.
+        for fan_in in [MAX_FAN_IN, 2] {
+            let mut sorter = ExternalSorter::new(&env::temp_dir(), 2)?;
+            sorter.fan_in = fan_in;
+            let dir = sorter.dir.clone();
+
+            // Two interleaved packets (tracked by their first timestamp) and
+            // an untracked event.
+            for (ts, tracking) in [
+                (1, Some((1, 0))),
+                (2, Some((2, 0))),
+                (3, Some((1, 1))),
+                (4, None),
+                (5, Some((2, 1))),
+                (6, Some((1, 2))),
+                (7, Some((2, 2))),
+                (8, Some((1, 3))),
+            ] {
+                sorter.add(event(ts, tracking))?;
+            }
+            assert_eq!(sorter.runs.len(), 4);
+
+            let mut out = Vec::new();
+            sorter.finish(|series| {
+                out.push(
+                    series
+                        .events
+                        .iter()
+                        .map(|e| {
+                            e.get_section::<CommonEvent>(SectionId::Common)
+                                .unwrap()
+                                .timestamp
+                        })
+                        .collect::<Vec<_>>(),
+                );
+                Ok(())
+            })?;
+
+            assert_eq!(out, vec![vec![1, 3, 6, 8], vec![2, 5, 7], vec![4]]);
+            assert!(!dir.exists());
+        }
+        Ok(())
+    }
+}